@@ -0,0 +1,384 @@
+use crate::check_status::CheckStatus;
+use crate::graphql;
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// How many passing runs of each check we remember.
+const MAX_CHECK_DURATIONS: usize = 10;
+
+/// How many passing runs of a check we need to see before we'll say how long
+/// it usually takes.
+const MIN_CHECK_DURATIONS: usize = 3;
+
+/// The last PRs we successfully loaded for each query, so we have something
+/// to show when GitHub can't be reached.
+#[derive(Debug)]
+pub struct Cache {
+    path: Option<PathBuf>,
+    file: CacheFile,
+    stale: Option<Stale>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct CacheFile {
+    /// Keyed by API URL and query
+    #[serde(default)]
+    entries: BTreeMap<String, Entry>,
+
+    /// Keyed by API URL
+    #[serde(default)]
+    rate_limits: BTreeMap<String, graphql::RateLimit>,
+
+    /// Recent passing runs, keyed by repository and check name
+    #[serde(default)]
+    check_durations: BTreeMap<String, Vec<CheckDuration>>,
+
+    /// The last menu action that failed. xbar ignores what actions print,
+    /// so we show this the next time the menu loads instead.
+    #[serde(default)]
+    action_failure: Option<ActionFailure>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ActionFailure {
+    pub failed_at: DateTime<Utc>,
+
+    /// The error, then each of its causes
+    pub messages: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CheckDuration {
+    /// The check run, so we only count each one once
+    url: String,
+    seconds: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    fetched_at: DateTime<Utc>,
+    pull_requests: Vec<Value>,
+}
+
+/// Set when we had to show cached PRs instead of fresh ones.
+#[derive(Debug)]
+pub struct Stale {
+    /// When the oldest cached data we're showing was fetched.
+    pub since: DateTime<Utc>,
+
+    /// Why we couldn't get fresh data.
+    pub reason: String,
+}
+
+impl Cache {
+    /// The cache file we use if one isn't specified in the config.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("cache.json"))
+    }
+
+    /// Load the cache file at `path`. A missing or unreadable cache isn't
+    /// worth failing over, so in those cases we start from scratch.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file = match &path {
+            Some(path) if path.exists() => fs::read_to_string(path)
+                .context("could not read the cache file")
+                .and_then(|text| {
+                    serde_json::from_str(&text).context("could not parse the cache file")
+                })
+                .unwrap_or_else(|err| {
+                    log::warn!("{err:?}");
+                    CacheFile::default()
+                }),
+            _ => CacheFile::default(),
+        };
+
+        Cache {
+            path,
+            file,
+            stale: None,
+        }
+    }
+
+    /// Remember the PRs we just loaded for `key`. If every one of them failed
+    /// to load, we keep what we had before instead.
+    pub fn store(&mut self, key: &str, prs: &[Result<graphql::PullRequest>]) -> Result<()> {
+        if !prs.is_empty() && prs.iter().all(Result::is_err) {
+            log::warn!("not caching {key}, since none of its PRs loaded");
+            return Ok(());
+        }
+
+        let mut pull_requests = Vec::with_capacity(prs.len());
+        for pr in prs.iter().flatten() {
+            pull_requests
+                .push(serde_json::to_value(pr).context("could not serialize a PR for the cache")?);
+            self.record_check_durations(pr);
+        }
+
+        self.file.entries.insert(
+            key.to_string(),
+            Entry {
+                fetched_at: Utc::now(),
+                pull_requests,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn record_check_durations(&mut self, pr: &graphql::PullRequest) {
+        for run in pr.check_runs() {
+            let (started_at, completed_at) =
+                match (run.conclusion.as_ref(), run.started_at, run.completed_at) {
+                    (Some(CheckStatus::Success), Some(started_at), Some(completed_at)) => {
+                        (started_at, completed_at)
+                    }
+                    _ => continue,
+                };
+
+            let durations = self
+                .file
+                .check_durations
+                .entry(check_key(&run.url, &run.name))
+                .or_default();
+            if durations.iter().any(|duration| duration.url == run.url) {
+                continue;
+            }
+
+            durations.push(CheckDuration {
+                url: run.url.clone(),
+                seconds: (completed_at - started_at).num_seconds(),
+            });
+            if durations.len() > MAX_CHECK_DURATIONS {
+                durations.remove(0);
+            }
+        }
+    }
+
+    /// The median time the check run at `url` has taken to pass recently.
+    pub fn usual_check_duration(&self, url: &str, name: &str) -> Option<TimeDelta> {
+        let durations = self.file.check_durations.get(&check_key(url, name))?;
+        if durations.len() < MIN_CHECK_DURATIONS {
+            return None;
+        }
+
+        let mut seconds: Vec<i64> = durations.iter().map(|duration| duration.seconds).collect();
+        seconds.sort_unstable();
+        Some(TimeDelta::seconds(seconds[seconds.len() / 2]))
+    }
+
+    /// Get the last PRs we loaded for `key`, marking the cache as stale
+    /// because of `err`. If we've never loaded anything for `key`, we give
+    /// `err` back.
+    pub fn fall_back(
+        &mut self,
+        key: &str,
+        err: anyhow::Error,
+    ) -> Result<Vec<Result<graphql::PullRequest>>> {
+        let entry = match self.file.entries.get(key) {
+            Some(entry) => entry,
+            None => return Err(err),
+        };
+
+        log::warn!("using cached PRs for {key}: {err:?}");
+
+        if !matches!(&self.stale, Some(stale) if stale.since <= entry.fetched_at) {
+            self.stale = Some(Stale {
+                since: entry.fetched_at,
+                reason: format!("{err:#}"),
+            });
+        }
+
+        Ok(entry
+            .pull_requests
+            .iter()
+            .map(graphql::PullRequest::from_value)
+            .collect())
+    }
+
+    /// The last rate limit information we got for the host at `api_url`.
+    pub fn rate_limit(&self, api_url: &str) -> Option<graphql::RateLimit> {
+        self.file.rate_limits.get(api_url).cloned()
+    }
+
+    pub fn set_rate_limit(&mut self, api_url: &str, rate_limit: graphql::RateLimit) {
+        self.file
+            .rate_limits
+            .insert(api_url.to_string(), rate_limit);
+    }
+
+    /// Remember that a menu action failed with `err`.
+    pub fn set_action_failure(&mut self, err: &anyhow::Error) {
+        self.file.action_failure = Some(ActionFailure {
+            failed_at: Utc::now(),
+            messages: err.chain().map(ToString::to_string).collect(),
+        });
+    }
+
+    /// The last menu action failure, if we haven't shown it yet. Once taken,
+    /// it's gone the next time we save.
+    pub fn take_action_failure(&mut self) -> Option<ActionFailure> {
+        self.file.action_failure.take()
+    }
+
+    pub fn stale(&self) -> Option<&Stale> {
+        self.stale.as_ref()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("could not create {}", parent.display()))?;
+        }
+
+        fs::write(
+            path,
+            serde_json::to_string(&self.file).context("could not serialize the cache")?,
+        )
+        .with_context(|| format!("could not write the cache to {}", path.display()))
+    }
+}
+
+/// Check names are only unique within a repository, so we key durations by
+/// both. Check run URLs start with the repository's URL.
+fn check_key(url: &str, name: &str) -> String {
+    let repo = url.splitn(6, '/').take(5).collect::<Vec<_>>().join("/");
+    format!("{repo} {name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn fixture() -> graphql::PullRequest {
+        graphql::PullRequest::from_value(
+            &serde_json::from_str(include_str!("test_fixtures/pr_approved.json")).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn falls_back_to_stored_prs() {
+        let mut cache = Cache::load(None);
+        cache.store("key", &[Ok(fixture())]).unwrap();
+
+        let prs = cache.fall_back("key", anyhow!("offline")).unwrap();
+
+        assert_eq!(1, prs.len());
+        assert_eq!(1, prs[0].as_ref().unwrap().number);
+        assert_eq!("offline", cache.stale().unwrap().reason);
+    }
+
+    #[test]
+    fn gives_back_the_error_without_an_entry() {
+        let mut cache = Cache::load(None);
+
+        assert_eq!(
+            "offline",
+            cache
+                .fall_back("key", anyhow!("offline"))
+                .unwrap_err()
+                .to_string()
+        );
+        assert!(cache.stale().is_none());
+    }
+
+    #[test]
+    fn fresh_without_falling_back() {
+        let mut cache = Cache::load(None);
+        cache.store("key", &[Ok(fixture())]).unwrap();
+
+        assert!(cache.stale().is_none());
+    }
+
+    #[test]
+    fn keeps_stored_prs_when_every_pr_failed() {
+        let mut cache = Cache::load(None);
+        cache.store("key", &[Ok(fixture())]).unwrap();
+        cache
+            .store("key", &[Err(anyhow!("broken")), Err(anyhow!("broken"))])
+            .unwrap();
+
+        let prs = cache.fall_back("key", anyhow!("offline")).unwrap();
+
+        assert_eq!(1, prs.len());
+    }
+
+    #[test]
+    fn action_failure_shows_once() {
+        let path = std::env::temp_dir().join(format!(
+            "xbar-pr-status-action-failure-{}.json",
+            std::process::id()
+        ));
+
+        let mut cache = Cache::load(Some(path.clone()));
+        cache.set_action_failure(&anyhow!("not mergeable").context("could not merge PR_1"));
+        cache.save().unwrap();
+
+        let mut cache = Cache::load(Some(path.clone()));
+        assert_eq!(
+            vec!["could not merge PR_1", "not mergeable"],
+            cache.take_action_failure().unwrap().messages
+        );
+        cache.save().unwrap();
+
+        assert!(Cache::load(Some(path.clone()))
+            .take_action_failure()
+            .is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn usual_check_duration() {
+        let mut cache = Cache::load(None);
+        for (run, minutes) in [(1, 4), (2, 5), (3, 20)] {
+            cache
+                .file
+                .check_durations
+                .entry(check_key("https://github.com/org/repo/runs/0", "build"))
+                .or_default()
+                .push(CheckDuration {
+                    url: format!("https://github.com/org/repo/runs/{run}"),
+                    seconds: minutes * 60,
+                });
+        }
+
+        assert_eq!(
+            Some(TimeDelta::minutes(5)),
+            cache.usual_check_duration("https://github.com/org/repo/runs/4", "build")
+        );
+        assert_eq!(
+            None,
+            cache.usual_check_duration("https://github.com/org/other/runs/4", "build")
+        );
+    }
+
+    #[test]
+    fn records_each_passing_run_once() {
+        let mut value: Value =
+            serde_json::from_str(include_str!("test_fixtures/pr_approved.json")).unwrap();
+        value["commits"]["nodes"][0]["commit"]["checkSuites"]["nodes"][0]["checkRuns"]["nodes"]
+            [0]["startedAt"] = "2022-01-18T18:00:00Z".into();
+        value["commits"]["nodes"][0]["commit"]["checkSuites"]["nodes"][0]["checkRuns"]["nodes"]
+            [0]["completedAt"] = "2022-01-18T18:04:12Z".into();
+        let pr = graphql::PullRequest::from_value(&value).unwrap();
+
+        let mut cache = Cache::load(None);
+        cache.record_check_durations(&pr);
+        cache.record_check_durations(&pr);
+
+        let durations = &cache.file.check_durations["https://github.com/org/repo Check 1"];
+        assert_eq!(1, durations.len());
+        assert_eq!(252, durations[0].seconds);
+    }
+}
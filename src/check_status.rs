@@ -1,4 +1,5 @@
 use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 use std::str::FromStr;
 
@@ -18,6 +19,60 @@ pub enum CheckStatus {
     Skipped,
     StartupFailure,
     Stale,
+
+    // check runs that haven't concluded yet, from their status
+    /// Waiting for a runner to pick the check up
+    Queued,
+    InProgress,
+    /// Waiting on a deployment protection rule, like a manual approval
+    Waiting,
+    Requested,
+}
+
+impl CheckStatus {
+    /// The name GitHub uses for this status; the inverse of `from_str`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ActionRequired => "ACTION_REQUIRED",
+            Self::Cancelled => "CANCELLED",
+            Self::Error => "ERROR",
+            Self::Expected => "EXPECTED",
+            Self::Failure => "FAILURE",
+            Self::Neutral => "NEUTRAL",
+            Self::Pending => "PENDING",
+            Self::Skipped => "SKIPPED",
+            Self::Stale => "STALE",
+            Self::StartupFailure => "STARTUP_FAILURE",
+            Self::Success => "SUCCESS",
+            Self::TimedOut => "TIMED_OUT",
+            Self::Queued => "QUEUED",
+            Self::InProgress => "IN_PROGRESS",
+            Self::Waiting => "WAITING",
+            Self::Requested => "REQUESTED",
+        }
+    }
+
+    /// Whether the check hasn't finished yet.
+    pub fn is_pending(&self) -> bool {
+        matches!(
+            self,
+            Self::Pending | Self::Queued | Self::InProgress | Self::Waiting | Self::Requested
+        )
+    }
+
+    /// Whether this means the check didn't pass.
+    pub fn is_failing(&self) -> bool {
+        matches!(
+            self,
+            Self::ActionRequired
+                | Self::Cancelled
+                | Self::Error
+                | Self::Failure
+                | Self::StartupFailure
+                | Self::Stale
+                | Self::TimedOut
+        )
+    }
 }
 
 impl FromStr for CheckStatus {
@@ -37,6 +92,10 @@ impl FromStr for CheckStatus {
             "STARTUP_FAILURE" => Ok(Self::StartupFailure),
             "SUCCESS" => Ok(Self::Success),
             "TIMED_OUT" => Ok(Self::TimedOut),
+            "QUEUED" => Ok(Self::Queued),
+            "IN_PROGRESS" => Ok(Self::InProgress),
+            "WAITING" => Ok(Self::Waiting),
+            "REQUESTED" => Ok(Self::Requested),
             _ => bail!("got unexpected value {} as a CheckStatus", s),
         }
     }
@@ -61,6 +120,19 @@ impl TryFrom<&Value> for CheckStatus {
     }
 }
 
+impl<'de> Deserialize<'de> for CheckStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for CheckStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,6 +230,22 @@ mod tests {
             assert_eq!(CheckStatus::Stale, CheckStatus::from_str("STALE").unwrap())
         }
 
+        #[test]
+        fn queued() {
+            assert_eq!(
+                CheckStatus::Queued,
+                CheckStatus::from_str("QUEUED").unwrap()
+            )
+        }
+
+        #[test]
+        fn in_progress() {
+            assert_eq!(
+                CheckStatus::InProgress,
+                CheckStatus::from_str("IN_PROGRESS").unwrap()
+            )
+        }
+
         #[test]
         fn other_string() {
             assert_eq!(
@@ -167,6 +255,34 @@ mod tests {
         }
     }
 
+    mod as_str {
+        use super::*;
+
+        #[test]
+        fn round_trips() {
+            for name in [
+                "ACTION_REQUIRED",
+                "CANCELLED",
+                "ERROR",
+                "EXPECTED",
+                "FAILURE",
+                "NEUTRAL",
+                "PENDING",
+                "SKIPPED",
+                "STALE",
+                "STARTUP_FAILURE",
+                "SUCCESS",
+                "TIMED_OUT",
+                "QUEUED",
+                "IN_PROGRESS",
+                "WAITING",
+                "REQUESTED",
+            ] {
+                assert_eq!(name, CheckStatus::from_str(name).unwrap().as_str())
+            }
+        }
+    }
+
     mod from_value {
         use super::*;
         use serde_json::json;
@@ -0,0 +1,93 @@
+//! Copying text to the clipboard from menu items. Each platform has its own
+//! clipboard tool, so we try the usual ones until one works.
+
+use anyhow::{bail, Context, Result};
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Put `text` on the clipboard. `command` is a shell command that reads the
+/// text from stdin; without one, we look for a clipboard tool we know.
+pub fn copy(text: &str, command: Option<&str>) -> Result<()> {
+    if let Some(command) = command {
+        return match run("sh", &["-c", command], text)? {
+            true => Ok(()),
+            false => bail!("could not find sh to run `{command}`"),
+        };
+    }
+
+    for (program, args) in tools() {
+        if run(program, args, text)? {
+            return Ok(());
+        }
+    }
+
+    bail!("could not find pbcopy, wl-copy, xclip, or xsel. Use --copy-command to tell me how to copy text.")
+}
+
+/// Clipboard tools we know how to use, in the order we try them.
+fn tools() -> Vec<(&'static str, &'static [&'static str])> {
+    let mut tools: Vec<(&str, &[&str])> = vec![("pbcopy", &[])];
+
+    // wl-copy needs a Wayland session; under X11 we want xclip or xsel
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(("wl-copy", &[]));
+    }
+
+    tools.push(("xclip", &["-selection", "clipboard"]));
+    tools.push(("xsel", &["--clipboard", "--input"]));
+    tools
+}
+
+/// Run `program` with `text` on stdin. Returns `false` if there's no such
+/// program, so the caller can try another.
+fn run(program: &str, args: &[&str], text: &str) -> Result<bool> {
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).with_context(|| format!("could not run {program}")),
+    };
+
+    child
+        .stdin
+        .take()
+        .context("could not open stdin")?
+        .write_all(text.as_bytes())
+        .with_context(|| format!("could not write to {program}"))?;
+
+    let status = child
+        .wait()
+        .with_context(|| format!("could not wait for {program}"))?;
+    if !status.success() {
+        bail!("{program} exited with {status}");
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn configured_command() {
+        let path = std::env::temp_dir().join(format!("xbar-pr-status-copy-{}", std::process::id()));
+
+        copy("#1", Some(&format!("cat > '{}'", path.display()))).unwrap();
+
+        assert_eq!("#1", fs::read_to_string(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn failing_command() {
+        assert_eq!(
+            "sh exited with exit status: 1",
+            copy("#1", Some("exit 1")).unwrap_err().to_string()
+        )
+    }
+}
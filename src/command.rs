@@ -0,0 +1,120 @@
+//! Subcommands the menu runs when you click on an action. Most make a single
+//! change through the GitHub API, after which xbar refreshes the menu.
+
+use crate::clipboard;
+use crate::github::GitHub;
+use crate::graphql::MergeMethod;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Re-run the failed jobs in a check suite
+    Rerun {
+        /// The check suite's node ID
+        #[clap(long)]
+        check_suite: String,
+    },
+
+    /// Merge a pull request now
+    Merge {
+        /// The pull request's node ID
+        #[clap(long)]
+        pull_request: String,
+
+        #[clap(long, value_enum)]
+        method: MergeMethod,
+    },
+
+    /// Merge a pull request once its requirements are met
+    AutoMerge {
+        /// The pull request's node ID
+        #[clap(long)]
+        pull_request: String,
+
+        #[clap(long, value_enum)]
+        method: MergeMethod,
+    },
+
+    /// Add a pull request to its base branch's merge queue
+    Enqueue {
+        /// The pull request's node ID
+        #[clap(long)]
+        pull_request: String,
+    },
+
+    /// Mark a draft pull request as ready for review
+    Ready {
+        /// The pull request's node ID
+        #[clap(long)]
+        pull_request: String,
+    },
+
+    /// Convert a pull request back to a draft
+    Draft {
+        /// The pull request's node ID
+        #[clap(long)]
+        pull_request: String,
+    },
+
+    /// Ask someone to review a pull request
+    RequestReview {
+        /// The pull request's node ID
+        #[clap(long)]
+        pull_request: String,
+
+        /// A user's login or a team's slug
+        #[clap(long)]
+        reviewer: String,
+    },
+
+    /// Copy text to the clipboard
+    Copy {
+        text: String,
+
+        /// A shell command to copy with, reading the text from stdin
+        #[clap(long)]
+        command: Option<String>,
+    },
+}
+
+impl Command {
+    /// Run the command. `github` makes a client for the host the menu item
+    /// is about; copying doesn't talk to GitHub, so it never asks for one.
+    pub fn run(&self, github: impl FnOnce() -> Result<GitHub>) -> Result<()> {
+        match self {
+            Command::Rerun { check_suite } => github()?
+                .rerun_check_suite(check_suite)
+                .with_context(|| format!("could not re-run check suite {check_suite}")),
+            Command::Merge {
+                pull_request,
+                method,
+            } => github()?
+                .merge_pull_request(pull_request, *method)
+                .with_context(|| format!("could not merge {pull_request}")),
+            Command::AutoMerge {
+                pull_request,
+                method,
+            } => github()?
+                .enable_auto_merge(pull_request, *method)
+                .with_context(|| format!("could not enable auto-merge for {pull_request}")),
+            Command::Enqueue { pull_request } => github()?
+                .enqueue_pull_request(pull_request)
+                .with_context(|| format!("could not add {pull_request} to the merge queue")),
+            Command::Ready { pull_request } => github()?
+                .mark_ready_for_review(pull_request)
+                .with_context(|| format!("could not mark {pull_request} ready for review")),
+            Command::Draft { pull_request } => github()?
+                .convert_to_draft(pull_request)
+                .with_context(|| format!("could not convert {pull_request} to a draft")),
+            Command::RequestReview {
+                pull_request,
+                reviewer,
+            } => github()?
+                .request_review(pull_request, reviewer)
+                .with_context(|| format!("could not request a review from {reviewer}")),
+            Command::Copy { text, command } => clipboard::copy(text, command.as_deref())
+                .with_context(|| format!("could not copy {text}")),
+        }
+    }
+}
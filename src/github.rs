@@ -0,0 +1,2214 @@
+use crate::check_status::CheckStatus;
+use crate::graphql;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::Parser;
+use reqwest::blocking::Client;
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How many pull requests we ask for in a single request. GitHub caps this at
+/// 100, but each PR brings a lot of nested checks along with it so we stay
+/// well under that.
+const PAGE_SIZE: usize = 25;
+
+/// How many PRs (or check runs) we ask about at once in the follow-up
+/// queries. Fewer requests keep each run short, but GitHub limits how many
+/// nodes one query can touch.
+const BATCH_SIZE: usize = 10;
+
+/// The longest we'll wait between retries, however many there are. Past
+/// this, xbar would start another run before we finished this one.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+const PULL_REQUESTS_QUERY: &str = concat!(
+    include_str!("pull_requests.graphql"),
+    include_str!("pull_request_fields.graphql"),
+    include_str!("check_suite_fields.graphql"),
+    include_str!("check_run_fields.graphql"),
+);
+
+const SEARCH_QUERY: &str = concat!(
+    include_str!("search.graphql"),
+    include_str!("pull_request_fields.graphql"),
+    include_str!("check_suite_fields.graphql"),
+    include_str!("check_run_fields.graphql"),
+);
+
+const CHECK_SUITES_QUERY: &str = concat!(
+    include_str!("check_suites.graphql"),
+    include_str!("check_suite_fields.graphql"),
+    include_str!("check_run_fields.graphql"),
+);
+
+const CHECK_RUNS_QUERY: &str = concat!(
+    include_str!("check_runs.graphql"),
+    include_str!("check_run_fields.graphql"),
+);
+
+const REVIEW_THREADS_QUERY: &str = include_str!("review_threads.graphql");
+
+/// The rate limit fields every other query asks for at the end
+const RATE_LIMIT_SELECTION: &str = "rateLimit { cost limit remaining resetAt }";
+
+/// One PR's worth of required checks, for `query_batch`
+const REQUIRED_CHECKS_SELECTION: &str = include_str!("required_checks.graphql");
+
+/// One failing check run's details, for `query_batch`
+const CHECK_RUN_DETAILS_SELECTION: &str = include_str!("check_run_details.graphql");
+
+const CHECK_SUITE_QUERY: &str = include_str!("check_suite.graphql");
+
+const RERUN_CHECK_SUITE_MUTATION: &str = include_str!("rerequest_check_suite.graphql");
+
+const MERGE_PULL_REQUEST_MUTATION: &str = include_str!("merge_pull_request.graphql");
+
+const ENABLE_AUTO_MERGE_MUTATION: &str = include_str!("enable_auto_merge.graphql");
+
+const ENQUEUE_PULL_REQUEST_MUTATION: &str = include_str!("enqueue_pull_request.graphql");
+
+const MARK_READY_FOR_REVIEW_MUTATION: &str = include_str!("mark_ready_for_review.graphql");
+
+const CONVERT_TO_DRAFT_MUTATION: &str = include_str!("convert_to_draft.graphql");
+
+const REVIEWER_IDS_QUERY: &str = include_str!("reviewer_ids.graphql");
+
+const REQUEST_REVIEWS_MUTATION: &str = include_str!("request_reviews.graphql");
+
+/// An additional GitHub instance (for example a GitHub Enterprise Server) to
+/// load PRs from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub api_url: String,
+    pub api_token: String,
+}
+
+impl FromStr for Host {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (api_url, api_token) = s.rsplit_once('=').with_context(|| {
+            format!("I couldn't find a `=` between the API URL and token in `{s}`")
+        })?;
+
+        Ok(Host {
+            api_url: api_url.to_string(),
+            api_token: api_token.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct Options {
+    /// Once GitHub says we have this many API points left, stop making
+    /// requests and show cached PRs until the rate limit resets. Leaves some
+    /// room for other tools (or other machines) using the same token.
+    #[clap(long, env = "RATE_LIMIT_RESERVE", default_value = "100")]
+    rate_limit_reserve: u64,
+
+    /// Give up connecting to GitHub after this many seconds
+    #[clap(long, env = "CONNECT_TIMEOUT", default_value = "10")]
+    connect_timeout: u64,
+
+    /// Give up on any single request to GitHub after this many seconds,
+    /// including reading the response
+    #[clap(long, env = "TIMEOUT", default_value = "30")]
+    timeout: u64,
+
+    /// How many times to retry a request after a connection error or a 5xx
+    /// response from GitHub
+    #[clap(long, env = "RETRIES", default_value = "2")]
+    retries: u32,
+
+    /// How long to wait before the first retry, in milliseconds. This doubles
+    /// with each retry after that, up to 10 seconds.
+    #[clap(long, env = "RETRY_DELAY_MS", default_value = "500")]
+    retry_delay_ms: u64,
+}
+
+pub struct GitHub {
+    client: Client,
+    api_url: String,
+    api_token: String,
+    rate_limit_reserve: u64,
+    rate_limit: RefCell<Option<graphql::RateLimit>>,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+impl GitHub {
+    /// Make a client for the GraphQL API at `api_url`. `rate_limit` is the
+    /// last rate limit we saw for this host, if any; we won't make requests
+    /// that would dip into the reserve until it resets.
+    pub fn new(
+        api_url: &str,
+        api_token: &str,
+        options: &Options,
+        rate_limit: Option<graphql::RateLimit>,
+    ) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .connect_timeout(Duration::from_secs(options.connect_timeout))
+            .timeout(Duration::from_secs(options.timeout))
+            .build()
+            .context("could not build the HTTP client")?;
+
+        Ok(GitHub {
+            client,
+            api_url: api_url.to_string(),
+            api_token: api_token.to_string(),
+            rate_limit_reserve: options.rate_limit_reserve,
+            rate_limit: RefCell::new(rate_limit),
+            retries: options.retries,
+            retry_delay: Duration::from_millis(options.retry_delay_ms),
+        })
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// Load the viewer's open pull requests, following pagination cursors
+    /// (including for nested check suites and check runs) until we either run
+    /// out of PRs or hit `limit`.
+    pub fn pull_requests(&self, limit: usize) -> Result<Vec<Result<graphql::PullRequest>>> {
+        let mut out: Vec<Value> = Vec::new();
+        let mut before: Option<String> = None;
+
+        while out.len() < limit {
+            let data: PullRequestsData = self
+                .query(
+                    PULL_REQUESTS_QUERY,
+                    json!({
+                        "pageSize": PAGE_SIZE.min(limit - out.len()),
+                        "before": before,
+                    }),
+                )
+                .context("could not fetch a page of pull requests")?;
+            let page = data.viewer.pull_requests;
+
+            // we're paging backwards from the most recent PRs, so each page
+            // goes in front of the ones we've already seen.
+            let mut nodes = page.nodes;
+            nodes.append(&mut out);
+            out = nodes;
+
+            if !page.page_info.has_previous_page {
+                break;
+            }
+
+            before = page.page_info.start_cursor;
+        }
+
+        Ok(self.load_pull_requests(&out))
+    }
+
+    /// Load pull requests matching a GitHub issue search query (e.g.
+    /// `is:open is:pr review-requested:@me`), with the same pagination rules
+    /// as `pull_requests`.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<Result<graphql::PullRequest>>> {
+        let mut out: Vec<Value> = Vec::new();
+        let mut after: Option<String> = None;
+
+        while out.len() < limit {
+            let data: SearchData = self
+                .query(
+                    SEARCH_QUERY,
+                    json!({
+                        "query": query,
+                        "pageSize": PAGE_SIZE.min(limit - out.len()),
+                        "after": after,
+                    }),
+                )
+                .with_context(|| format!("could not fetch a page of results for `{query}`"))?;
+
+            // issues and discussions match the query too, but come back
+            // empty since we only ask for fields on PRs
+            out.extend(
+                data.search
+                    .nodes
+                    .into_iter()
+                    .filter(|node| node.as_object().is_some_and(|node| !node.is_empty())),
+            );
+
+            if !data.search.page_info.has_next_page {
+                break;
+            }
+
+            after = data.search.page_info.end_cursor;
+        }
+
+        Ok(self.load_pull_requests(&out))
+    }
+
+    /// Load each PR node separately, so that one malformed PR (or one we
+    /// can't load the rest of) doesn't keep us from showing the rest.
+    fn load_pull_requests(&self, nodes: &[Value]) -> Vec<Result<graphql::PullRequest>> {
+        let mut out: Vec<Result<graphql::PullRequest>> = nodes
+            .iter()
+            .map(|node| {
+                let mut pr = graphql::PullRequest::from_value(node)
+                    .inspect_err(|_| log::debug!("{:#?}", node))?;
+
+                self.load_remaining_checks(&mut pr).with_context(|| {
+                    format!("could not load all the checks for PR #{}", pr.number)
+                })?;
+
+                if let Err(err) = self.load_remaining_threads(&mut pr) {
+                    log::warn!(
+                        "could not load all the review threads for PR #{}: {err:?}",
+                        pr.number
+                    );
+                }
+
+                Ok(pr)
+            })
+            .collect();
+
+        self.load_required_checks(&mut out);
+        self.load_failure_details(&mut out);
+
+        out
+    }
+
+    /// The first page of check suites and check runs comes along with each
+    /// PR, but busy commits can have more than that. Fill them in.
+    fn load_remaining_checks(&self, pr: &mut graphql::PullRequest) -> Result<()> {
+        let commit = match pr.last_commit_mut() {
+            Some(commit) => commit,
+            None => return Ok(()),
+        };
+
+        let suites = match &mut commit.check_suites {
+            Some(suites) => suites,
+            None => return Ok(()),
+        };
+
+        while suites.page_info.has_next_page {
+            let data: CheckSuitesData = self.query(
+                CHECK_SUITES_QUERY,
+                json!({
+                    "id": commit.id.as_ref().context("commit did not have an ID")?,
+                    "after": suites.page_info.end_cursor,
+                }),
+            )?;
+
+            suites.extend(data.node.check_suites);
+        }
+
+        for suite in suites.nodes.iter_mut() {
+            while suite.check_runs.page_info.has_next_page {
+                let data: CheckRunsData = self.query(
+                    CHECK_RUNS_QUERY,
+                    json!({
+                        "id": suite.id.as_ref().context("check suite did not have an ID")?,
+                        "after": suite.check_runs.page_info.end_cursor,
+                    }),
+                )?;
+
+                suite.check_runs.extend(data.node.check_runs);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Only the first 100 review threads come along with each PR, and an
+    /// unresolved one could be further along on a long-running PR. If we
+    /// can't load the rest, we count what we have.
+    fn load_remaining_threads(&self, pr: &mut graphql::PullRequest) -> Result<()> {
+        let threads = match &mut pr.review_threads {
+            Some(threads) => threads,
+            None => return Ok(()),
+        };
+
+        while threads.page_info.has_next_page {
+            let data: ReviewThreadsData = self.query(
+                REVIEW_THREADS_QUERY,
+                json!({
+                    "id": pr.id.as_ref().context("PR did not have an ID")?,
+                    "after": threads.page_info.end_cursor,
+                }),
+            )?;
+
+            threads.extend(data.node.review_threads);
+        }
+
+        Ok(())
+    }
+
+    /// Whether a check is required depends on the PR's base branch, so
+    /// `isRequired` needs the PR number. Fragments can't pass that along, so
+    /// we ask about a batch of PRs at a time, each with its own variables.
+    /// If we can't find out for a PR, we show it with every check required,
+    /// like before we knew about required checks.
+    fn load_required_checks(&self, prs: &mut [Result<graphql::PullRequest>]) {
+        fn rollup(
+            pr: &mut Result<graphql::PullRequest>,
+        ) -> Option<&mut graphql::StatusCheckRollup> {
+            pr.as_mut()
+                .ok()?
+                .last_commit_mut()?
+                .status_check_rollup
+                .as_mut()
+        }
+
+        let mut pending: Vec<(usize, Value)> = prs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pr)| {
+                let pr = pr.as_ref().ok()?;
+                let commit = pr.last_commit()?;
+                commit.status_check_rollup.as_ref()?;
+
+                Some((
+                    i,
+                    json!({ "id": commit.id.as_ref()?, "number": pr.number, "after": null }),
+                ))
+            })
+            .collect();
+
+        // a few commits have more than a page of checks, so we go around
+        // again for those until we've seen them all
+        while !pending.is_empty() {
+            let mut next_pages = Vec::new();
+
+            for batch in pending.chunks(BATCH_SIZE) {
+                let variables: Vec<Value> = batch
+                    .iter()
+                    .map(|(_, variables)| variables.clone())
+                    .collect();
+                let results = match self.query_batch(
+                    "RequiredChecks",
+                    REQUIRED_CHECKS_SELECTION,
+                    &[("id", "ID!"), ("number", "Int!"), ("after", "String")],
+                    &variables,
+                ) {
+                    Ok(results) => results,
+                    Err(err) => {
+                        log::warn!("could not load required checks: {err:?}");
+                        for (i, _) in batch {
+                            if let Some(rollup) = rollup(&mut prs[*i]) {
+                                rollup.contexts = None;
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                for ((i, variables), result) in batch.iter().zip(results) {
+                    let node = match result
+                        .and_then(|node| graphql::from_value::<Option<RequiredChecksNode>>(&node))
+                    {
+                        Ok(node) => node,
+                        Err(err) => {
+                            log::warn!(
+                                "could not load required checks for PR #{}: {err:?}",
+                                variables["number"]
+                            );
+                            // a partial list would make the rest look optional
+                            if let Some(rollup) = rollup(&mut prs[*i]) {
+                                rollup.contexts = None;
+                            }
+                            continue;
+                        }
+                    };
+
+                    let page = match node.and_then(|node| node.status_check_rollup) {
+                        Some(rollup) => rollup.contexts,
+                        None => continue,
+                    };
+
+                    if page.page_info.has_next_page {
+                        let mut variables = variables.clone();
+                        variables["after"] = json!(page.page_info.end_cursor);
+                        next_pages.push((*i, variables));
+                    }
+
+                    if let Some(rollup) = rollup(&mut prs[*i]) {
+                        match &mut rollup.contexts {
+                            Some(contexts) => contexts.extend(page),
+                            None => rollup.contexts = Some(page),
+                        }
+                    }
+                }
+            }
+
+            pending = next_pages;
+        }
+    }
+
+    /// Load the title, summary, and first few annotations of each failing
+    /// check run. These are too expensive to ask for on every run up front,
+    /// so we ask about the failing ones a batch at a time afterwards.
+    fn load_failure_details(&self, prs: &mut [Result<graphql::PullRequest>]) {
+        let failing: Vec<(usize, String)> = prs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pr)| Some((i, pr.as_ref().ok()?)))
+            .flat_map(|(i, pr)| {
+                pr.check_runs()
+                    .filter(|run| run.conclusion.as_ref().is_some_and(CheckStatus::is_failing))
+                    .filter_map(move |run| Some((i, run.id.clone()?)))
+            })
+            .collect();
+
+        for batch in failing.chunks(BATCH_SIZE) {
+            let variables: Vec<Value> = batch.iter().map(|(_, id)| json!({ "id": id })).collect();
+            let results = match self.query_batch(
+                "CheckRunDetails",
+                CHECK_RUN_DETAILS_SELECTION,
+                &[("id", "ID!")],
+                &variables,
+            ) {
+                Ok(results) => results,
+                Err(err) => {
+                    log::warn!("could not load why checks failed: {err:?}");
+                    continue;
+                }
+            };
+
+            for ((i, id), result) in batch.iter().zip(results) {
+                let details = match result
+                    .and_then(|node| graphql::from_value::<Option<CheckRunDetailsNode>>(&node))
+                {
+                    Ok(Some(details)) => details,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        log::warn!("could not load why check run {id} failed: {err:?}");
+                        continue;
+                    }
+                };
+
+                let run = prs[*i]
+                    .as_mut()
+                    .ok()
+                    .and_then(|pr| pr.check_runs_mut().find(|run| run.id.as_ref() == Some(id)));
+                if let Some(run) = run {
+                    run.title = details.title;
+                    run.summary = details.summary;
+                    run.annotations = Some(details.annotations);
+                }
+            }
+        }
+    }
+
+    /// The base URL for GitHub's web UI on this host, for making links.
+    pub fn web_url(&self) -> String {
+        self.api_url
+            .trim_end_matches('/')
+            .trim_end_matches("/graphql")
+            .trim_end_matches("/api")
+            .replace("://api.github.com", "://github.com")
+    }
+
+    /// The base URL for GitHub's REST API on this host, for the few things
+    /// GraphQL can't do.
+    fn rest_url(&self) -> String {
+        let base = self
+            .api_url
+            .trim_end_matches('/')
+            .trim_end_matches("/graphql");
+
+        if base.ends_with("/api") {
+            format!("{base}/v3")
+        } else {
+            base.to_string()
+        }
+    }
+
+    /// Re-run the failed jobs in a check suite. GitHub Actions can re-run
+    /// just the jobs that failed; for other apps, we ask for the whole suite
+    /// again.
+    pub fn rerun_check_suite(&self, check_suite_id: &str) -> Result<()> {
+        let data: CheckSuiteData =
+            self.query(CHECK_SUITE_QUERY, json!({ "id": check_suite_id }))?;
+
+        match data.node.workflow_run {
+            Some(workflow_run) => {
+                let url = format!(
+                    "{}/repos/{}/actions/runs/{}/rerun-failed-jobs",
+                    self.rest_url(),
+                    data.node.repository.name_with_owner,
+                    workflow_run.database_id
+                );
+
+                let (status, headers, text) = self.post(&url, &json!({}))?;
+                if !status.is_success() {
+                    return Err(
+                        Error::from_response(status, &headers, &text, &self.web_url()).into(),
+                    );
+                }
+            }
+            None => self.mutate(
+                RERUN_CHECK_SUITE_MUTATION,
+                json!({
+                    "repositoryId": data.node.repository.id,
+                    "checkSuiteId": check_suite_id,
+                }),
+            )?,
+        }
+
+        Ok(())
+    }
+
+    pub fn merge_pull_request(
+        &self,
+        pull_request_id: &str,
+        method: graphql::MergeMethod,
+    ) -> Result<()> {
+        self.mutate(
+            MERGE_PULL_REQUEST_MUTATION,
+            json!({ "pullRequestId": pull_request_id, "mergeMethod": method }),
+        )
+    }
+
+    pub fn enable_auto_merge(
+        &self,
+        pull_request_id: &str,
+        method: graphql::MergeMethod,
+    ) -> Result<()> {
+        self.mutate(
+            ENABLE_AUTO_MERGE_MUTATION,
+            json!({ "pullRequestId": pull_request_id, "mergeMethod": method }),
+        )
+    }
+
+    pub fn enqueue_pull_request(&self, pull_request_id: &str) -> Result<()> {
+        self.mutate(
+            ENQUEUE_PULL_REQUEST_MUTATION,
+            json!({ "pullRequestId": pull_request_id }),
+        )
+    }
+
+    pub fn mark_ready_for_review(&self, pull_request_id: &str) -> Result<()> {
+        self.mutate(
+            MARK_READY_FOR_REVIEW_MUTATION,
+            json!({ "pullRequestId": pull_request_id }),
+        )
+    }
+
+    pub fn convert_to_draft(&self, pull_request_id: &str) -> Result<()> {
+        self.mutate(
+            CONVERT_TO_DRAFT_MUTATION,
+            json!({ "pullRequestId": pull_request_id }),
+        )
+    }
+
+    /// Ask `reviewer` to review a PR. Like `--reviewer-emoji`, `reviewer` is
+    /// either a user's login or a team's slug in the repository's
+    /// organization.
+    pub fn request_review(&self, pull_request_id: &str, reviewer: &str) -> Result<()> {
+        let data: ReviewerIdsData = self.query(
+            REVIEWER_IDS_QUERY,
+            json!({ "login": reviewer, "pullRequestId": pull_request_id }),
+        )?;
+
+        let team = data
+            .node
+            .and_then(|node| node.base_repository)
+            .and_then(|repo| repo.owner.team);
+
+        let (user_ids, team_ids) = match (data.user, team) {
+            (Some(user), _) => (vec![user.id], Vec::new()),
+            (None, Some(team)) => (Vec::new(), vec![team.id]),
+            (None, None) => bail!("could not find a user or team named {reviewer}"),
+        };
+
+        self.mutate(
+            REQUEST_REVIEWS_MUTATION,
+            json!({
+                "pullRequestId": pull_request_id,
+                "userIds": user_ids,
+                "teamIds": team_ids,
+            }),
+        )
+    }
+
+    /// Run a mutation. We don't need anything it sends back, but a failed
+    /// mutation comes back as `null`, so we ask for an object to make
+    /// `query` report GitHub's errors.
+    fn mutate(&self, mutation: &str, variables: Value) -> Result<()> {
+        let _: BTreeMap<String, serde_json::Map<String, Value>> =
+            self.query(mutation, variables)?;
+        Ok(())
+    }
+
+    /// The most recent rate limit information GitHub gave us.
+    pub fn rate_limit(&self) -> Option<graphql::RateLimit> {
+        self.rate_limit.borrow().clone()
+    }
+
+    /// Update our rate limit information with whatever GitHub sent back with
+    /// a response.
+    fn observe_rate_limit(&self, headers: &HeaderMap, body: Option<&Value>) {
+        let mut rate_limit = self.rate_limit.borrow_mut();
+
+        if let Some(from_headers) = rate_limit_from_headers(headers) {
+            *rate_limit = Some(graphql::RateLimit {
+                cost: rate_limit.as_ref().and_then(|previous| previous.cost),
+                ..from_headers
+            });
+        }
+
+        match body
+            .and_then(|body| body.pointer("/data/rateLimit"))
+            .map(graphql::from_value::<graphql::RateLimit>)
+        {
+            Some(Ok(from_body)) => *rate_limit = Some(from_body),
+            Some(Err(err)) => log::warn!("could not load rateLimit: {err:?}"),
+            None => (),
+        }
+    }
+
+    /// Make a GraphQL request and load the `data` it returns.
+    fn query<T: DeserializeOwned>(&self, query: &str, variables: Value) -> Result<T> {
+        let response = self.request(query, variables)?;
+
+        // GitHub can send back partial data alongside errors. If what we got
+        // is still usable we'll go with it, but otherwise the errors are a
+        // better explanation than whatever field happened to be missing.
+        match graphql::from_value(&response.data) {
+            Ok(data) => Ok(data),
+            Err(err) if response.errors.is_empty() => {
+                Err(err).context("could not load the data in GitHub's response")
+            }
+            Err(_) => Err(self
+                .graphql_error(&response.errors, &response.headers)
+                .into()),
+        }
+    }
+
+    /// Ask the same thing about several nodes in one request. `selection` is
+    /// the part of a query about one of them, using `variables` (names and
+    /// GraphQL types.) Each copy gets an alias and its own variables, taken
+    /// from the matching object in `items`. Returns whatever came back for
+    /// each item, or the errors GitHub gave for it instead.
+    fn query_batch(
+        &self,
+        name: &str,
+        selection: &str,
+        variables: &[(&str, &str)],
+        items: &[Value],
+    ) -> Result<Vec<Result<Value>>> {
+        let mut declarations = Vec::new();
+        let mut selections = Vec::new();
+        let mut values = serde_json::Map::new();
+
+        for (i, item) in items.iter().enumerate() {
+            let mut aliased = selection.to_string();
+            for (variable, type_) in variables {
+                declarations.push(format!("${variable}{i}: {type_}"));
+                aliased = aliased.replace(&format!("${variable}"), &format!("${variable}{i}"));
+                values.insert(
+                    format!("{variable}{i}"),
+                    item.get(variable).cloned().unwrap_or(Value::Null),
+                );
+            }
+            selections.push(format!("item{i}: {aliased}"));
+        }
+
+        let query = format!(
+            "query {name}({}) {{\n{}\n{RATE_LIMIT_SELECTION}\n}}",
+            declarations.join(", "),
+            selections.join("\n"),
+        );
+
+        let response = self.request(&query, Value::Object(values))?;
+        if !response.data.is_object() && !response.errors.is_empty() {
+            return Err(self
+                .graphql_error(&response.errors, &response.headers)
+                .into());
+        }
+
+        Ok((0..items.len())
+            .map(|i| {
+                let alias = format!("item{i}");
+                match response.data.get(&alias) {
+                    Some(data) if !data.is_null() => Ok(data.clone()),
+                    _ => {
+                        let errors: Vec<Value> = response
+                            .errors
+                            .iter()
+                            .filter(|err| {
+                                err.pointer("/path/0").and_then(Value::as_str)
+                                    == Some(alias.as_str())
+                            })
+                            .cloned()
+                            .collect();
+
+                        if errors.is_empty() {
+                            Ok(Value::Null)
+                        } else {
+                            Err(self.graphql_error(&errors, &response.headers).into())
+                        }
+                    }
+                }
+            })
+            .collect())
+    }
+
+    /// Make a GraphQL request, returning whatever data came back along with
+    /// any errors, for the caller to sort out.
+    fn request(&self, query: &str, variables: Value) -> Result<Response> {
+        if let Some(rate_limit) = self.rate_limit() {
+            if rate_limit.remaining <= self.rate_limit_reserve && rate_limit.reset_at > Utc::now() {
+                return Err(Error::RateLimited {
+                    reset_at: Some(rate_limit.reset_at),
+                }
+                .into());
+            }
+        }
+
+        let (status, headers, text) = self.post(
+            &self.api_url,
+            &json!({ "query": query, "variables": variables }),
+        )?;
+
+        log::debug!("body: {text}");
+
+        if !status.is_success() {
+            self.observe_rate_limit(&headers, None);
+            let err = Error::from_response(status, &headers, &text, &self.web_url());
+            if let Error::RateLimited {
+                reset_at: Some(reset_at),
+            } = err
+            {
+                self.back_off_until(reset_at);
+            }
+            return Err(err.into());
+        }
+
+        let mut body: Value = serde_json::from_str(&text).context("could not read JSON body")?;
+        self.observe_rate_limit(&headers, Some(&body));
+
+        let errors = match body.get("errors") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(errs)) => errs.clone(),
+            Some(_) => bail!("errors was not an array"),
+        };
+
+        for err in &errors {
+            log::error!("{}", err);
+        }
+
+        Ok(Response {
+            data: body.get_mut("data").map(Value::take).unwrap_or(Value::Null),
+            errors,
+            headers,
+        })
+    }
+
+    /// Explain GraphQL errors, backing off if they say we're rate limited.
+    fn graphql_error(&self, errors: &[Value], headers: &HeaderMap) -> Error {
+        let err = Error::from_graphql_errors(errors, headers, &self.web_url());
+        if let Error::RateLimited {
+            reset_at: Some(reset_at),
+        } = err
+        {
+            self.back_off_until(reset_at);
+        }
+        err
+    }
+
+    /// Send a request to the API, retrying with exponential backoff if we
+    /// can't connect or GitHub has a server error.
+    fn post(&self, url: &str, body: &Value) -> Result<(StatusCode, HeaderMap, String)> {
+        let authorization = header::HeaderValue::from_str(&format!("Bearer {}", self.api_token))
+            .context("could not create an Authorization header from the specified token")?;
+
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .client
+                .post(url)
+                .header(header::AUTHORIZATION, authorization.clone())
+                .json(body)
+                .send()
+                .and_then(|response| {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    Ok((status, headers, response.text()?))
+                });
+
+            // a request that timed out has already used up the time we'd
+            // want to wait for it, so trying again would only hold up the menu
+            let should_retry = match &result {
+                Ok((status, _, _)) => status.is_server_error(),
+                Err(err) => !err.is_builder() && !err.is_timeout(),
+            };
+
+            if !should_retry || attempt >= self.retries {
+                return Ok(result.map_err(Error::Network)?);
+            }
+
+            let delay = retry_delay(self.retry_delay, attempt);
+            match &result {
+                Ok((status, _, _)) => log::warn!("got {status}, retrying in {delay:?}"),
+                Err(err) => log::warn!("{err}, retrying in {delay:?}"),
+            }
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// Remember not to make any more requests until `reset_at`, even if the
+    /// headers said we had budget left (for example, when we hit a secondary
+    /// rate limit.)
+    fn back_off_until(&self, reset_at: DateTime<Utc>) {
+        let mut rate_limit = self.rate_limit.borrow_mut();
+        let previous = rate_limit.take();
+
+        *rate_limit = Some(graphql::RateLimit {
+            cost: previous.as_ref().and_then(|previous| previous.cost),
+            limit: previous.as_ref().and_then(|previous| previous.limit),
+            remaining: 0,
+            reset_at,
+        });
+    }
+}
+
+/// What GitHub sent back for a GraphQL request
+struct Response {
+    data: Value,
+    errors: Vec<Value>,
+    headers: HeaderMap,
+}
+
+/// The ways talking to GitHub commonly goes wrong, so that we can tell people
+/// what to do about it instead of dumping an error chain on them.
+#[derive(Debug)]
+pub enum Error {
+    /// GitHub didn't accept the token (HTTP 401.)
+    BadCredentials { tokens_url: String },
+
+    /// The token has to be authorized for an organization's SAML SSO before
+    /// it can see that organization's repos.
+    SsoRequired {
+        authorize_url: Option<String>,
+        tokens_url: String,
+    },
+
+    /// We've used up our API budget until `reset_at`.
+    RateLimited { reset_at: Option<DateTime<Utc>> },
+
+    /// We couldn't talk to GitHub at all.
+    Network(reqwest::Error),
+
+    /// Any other unsuccessful HTTP response.
+    Http { status: StatusCode, message: String },
+
+    /// GitHub answered, but with GraphQL errors instead of data.
+    GraphQL(Vec<String>),
+}
+
+impl Error {
+    /// Whether this is likely to go away on its own, such that showing
+    /// what we loaded last time is better than showing the error.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Network(_) | Error::RateLimited { .. } => true,
+            Error::Http { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    fn from_response(status: StatusCode, headers: &HeaderMap, text: &str, web_url: &str) -> Self {
+        let tokens_url = format!("{web_url}/settings/tokens");
+        let message = serde_json::from_str::<Value>(text)
+            .ok()
+            .and_then(|body| body.get("message")?.as_str().map(String::from))
+            .unwrap_or_else(|| {
+                status
+                    .canonical_reason()
+                    .unwrap_or("no message")
+                    .to_string()
+            });
+
+        match status {
+            StatusCode::UNAUTHORIZED => Error::BadCredentials { tokens_url },
+            StatusCode::FORBIDDEN if headers.contains_key("x-github-sso") => Error::SsoRequired {
+                authorize_url: sso_authorize_url(headers),
+                tokens_url,
+            },
+            StatusCode::TOO_MANY_REQUESTS => Error::RateLimited {
+                reset_at: rate_limit_reset(headers),
+            },
+            StatusCode::FORBIDDEN
+                if header_str(headers, "x-ratelimit-remaining") == Some("0")
+                    || message.to_lowercase().contains("rate limit") =>
+            {
+                Error::RateLimited {
+                    reset_at: rate_limit_reset(headers),
+                }
+            }
+            _ => Error::Http { status, message },
+        }
+    }
+
+    fn from_graphql_errors(errors: &[Value], headers: &HeaderMap, web_url: &str) -> Self {
+        let types: Vec<&str> = errors
+            .iter()
+            .filter_map(|err| err.get("type")?.as_str())
+            .collect();
+
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|err| match err.get("message").and_then(Value::as_str) {
+                Some(message) => message.to_string(),
+                None => err.to_string(),
+            })
+            .collect();
+
+        if types.contains(&"RATE_LIMITED") {
+            Error::RateLimited {
+                reset_at: rate_limit_reset(headers),
+            }
+        } else if headers.contains_key("x-github-sso")
+            || messages.iter().any(|message| message.contains("SAML"))
+        {
+            Error::SsoRequired {
+                authorize_url: sso_authorize_url(headers),
+                tokens_url: format!("{web_url}/settings/tokens"),
+            }
+        } else {
+            Error::GraphQL(messages)
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BadCredentials { .. } => write!(f, "GitHub did not accept the API token"),
+            Error::SsoRequired { .. } => write!(
+                f,
+                "the API token needs to be authorized for an organization's SAML SSO"
+            ),
+            Error::RateLimited {
+                reset_at: Some(reset_at),
+            } => {
+                write!(f, "hit GitHub's API rate limit, which resets at {reset_at}")
+            }
+            Error::RateLimited { reset_at: None } => write!(f, "hit GitHub's API rate limit"),
+            Error::Network(_) => write!(f, "could not reach GitHub"),
+            Error::Http { status, message } => {
+                write!(f, "GitHub responded with {status}: {message}")
+            }
+            Error::GraphQL(messages) => {
+                write!(f, "GitHub returned errors: {}", messages.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// How long to wait before retrying after `attempt` earlier retries:
+/// `first` doubled each time, up to `MAX_RETRY_DELAY`.
+fn retry_delay(first: Duration, attempt: u32) -> Duration {
+    2_u32
+        .checked_pow(attempt)
+        .and_then(|factor| first.checked_mul(factor))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// When we can make requests again. Secondary rate limits come with a
+/// `retry-after` header, which takes precedence over the primary reset time.
+fn rate_limit_reset(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    if let Some(seconds) = header_str(headers, "retry-after").and_then(|s| s.parse().ok()) {
+        return Some(Utc::now() + TimeDelta::seconds(seconds));
+    }
+
+    DateTime::from_timestamp(header_str(headers, "x-ratelimit-reset")?.parse().ok()?, 0)
+}
+
+fn rate_limit_from_headers(headers: &HeaderMap) -> Option<graphql::RateLimit> {
+    Some(graphql::RateLimit {
+        cost: None,
+        limit: header_str(headers, "x-ratelimit-limit").and_then(|s| s.parse().ok()),
+        remaining: header_str(headers, "x-ratelimit-remaining")?.parse().ok()?,
+        reset_at: DateTime::from_timestamp(
+            header_str(headers, "x-ratelimit-reset")?.parse().ok()?,
+            0,
+        )?,
+    })
+}
+
+/// GitHub tells us where to authorize a token in a header like
+/// `X-GitHub-SSO: required; url=https://github.com/orgs/...`
+fn sso_authorize_url(headers: &HeaderMap) -> Option<String> {
+    header_str(headers, "x-github-sso")?
+        .split(';')
+        .find_map(|part| part.trim().strip_prefix("url="))
+        .map(String::from)
+}
+
+#[derive(Debug, Deserialize)]
+struct PullRequestsData {
+    viewer: Viewer,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Viewer {
+    pull_requests: graphql::Connection<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchData {
+    search: graphql::Connection<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckSuitesData {
+    node: CheckSuitesNode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckSuitesNode {
+    check_suites: graphql::Connection<graphql::CheckSuite>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRunsData {
+    node: CheckRunsNode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckRunsNode {
+    check_runs: graphql::Connection<graphql::CheckRun>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewThreadsData {
+    node: ReviewThreadsNode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewThreadsNode {
+    review_threads: graphql::Connection<graphql::ReviewThread>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequiredChecksNode {
+    status_check_rollup: Option<RequiredChecksRollup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequiredChecksRollup {
+    contexts: graphql::Connection<graphql::RollupContext>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRunDetailsNode {
+    title: Option<String>,
+    summary: Option<String>,
+    annotations: graphql::Connection<graphql::CheckAnnotation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckSuiteData {
+    node: CheckSuiteNode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CheckSuiteNode {
+    workflow_run: Option<WorkflowRun>,
+    repository: Repository,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WorkflowRun {
+    database_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Repository {
+    id: String,
+    name_with_owner: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewerIdsData {
+    user: Option<NodeId>,
+    node: Option<ReviewerIdsNode>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewerIdsNode {
+    base_repository: Option<ReviewerIdsRepository>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewerIdsRepository {
+    owner: ReviewerIdsOwner,
+}
+
+/// Users own repositories too, but only organizations have teams.
+#[derive(Debug, Deserialize)]
+struct ReviewerIdsOwner {
+    team: Option<NodeId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodeId {
+    id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn options() -> Options {
+        Options::parse_from(["test"])
+    }
+
+    /// A stand-in for GitHub that answers each request it gets with the
+    /// next of `responses` (status and body.) If it runs out, it stops
+    /// answering altogether. Keeps the body of every request it gets.
+    fn serve<S: Into<String> + Send + 'static>(
+        responses: Vec<(u16, S)>,
+    ) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+
+        thread::spawn(move || {
+            let mut responses = responses.into_iter();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = read_request(&mut stream);
+                seen.lock().unwrap().push(request);
+
+                match responses.next().map(|(status, body)| (status, body.into())) {
+                    Some((status, body)) => write!(
+                        stream,
+                        "HTTP/1.1 {status} Whatever\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .unwrap(),
+                    // hang on to the connection without answering
+                    None => thread::sleep(Duration::from_secs(5)),
+                }
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn read_request(stream: &mut TcpStream) -> Value {
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn github(url: &str, args: &[&str]) -> GitHub {
+        let options =
+            Options::parse_from(["test", "--retry-delay-ms", "1"].iter().chain(args.iter()));
+        GitHub::new(url, "token", &options, None).unwrap()
+    }
+
+    mod retries {
+        use super::*;
+
+        #[test]
+        fn retries_server_errors() {
+            let (url, requests) = serve(vec![
+                (502, "{}"),
+                (503, "{}"),
+                (200, r#"{"data": {"ok": true}}"#),
+            ]);
+
+            let data: Value = github(&url, &[]).query("query", json!({})).unwrap();
+
+            assert_eq!(json!({"ok": true}), data);
+            assert_eq!(3, requests.lock().unwrap().len());
+        }
+
+        #[test]
+        fn gives_up_after_retries() {
+            let (url, requests) = serve(vec![(502, "{}"), (502, "{}"), (502, "{}")]);
+
+            let err = github(&url, &["--retries", "1"])
+                .query::<Value>("query", json!({}))
+                .unwrap_err();
+
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::Http {
+                    status: StatusCode::BAD_GATEWAY,
+                    ..
+                })
+            ));
+            assert_eq!(2, requests.lock().unwrap().len());
+        }
+
+        #[test]
+        fn does_not_retry_client_errors() {
+            let (url, requests) = serve(vec![(401, r#"{"message": "Bad credentials"}"#)]);
+
+            let err = github(&url, &[])
+                .query::<Value>("query", json!({}))
+                .unwrap_err();
+
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::BadCredentials { .. })
+            ));
+            assert_eq!(1, requests.lock().unwrap().len());
+        }
+
+        #[test]
+        fn doubles_the_delay() {
+            assert_eq!(
+                Duration::from_millis(2000),
+                retry_delay(Duration::from_millis(500), 2)
+            )
+        }
+
+        #[test]
+        fn caps_the_delay() {
+            assert_eq!(MAX_RETRY_DELAY, retry_delay(Duration::from_millis(500), 5));
+            assert_eq!(
+                MAX_RETRY_DELAY,
+                retry_delay(Duration::from_millis(500), u32::MAX)
+            );
+        }
+
+        #[test]
+        fn times_out() {
+            let (url, _) = serve(Vec::<(u16, &str)>::new());
+
+            let err = github(&url, &["--timeout", "1", "--retries", "0"])
+                .query::<Value>("query", json!({}))
+                .unwrap_err();
+
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::Network(err)) if err.is_timeout()
+            ));
+        }
+
+        #[test]
+        fn does_not_retry_timeouts() {
+            let (url, requests) = serve(Vec::<(u16, &str)>::new());
+
+            let started = std::time::Instant::now();
+            let err = github(&url, &["--timeout", "1", "--retries", "2"])
+                .query::<Value>("query", json!({}))
+                .unwrap_err();
+
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::Network(err)) if err.is_timeout()
+            ));
+            assert!(started.elapsed() < Duration::from_secs(2));
+            assert_eq!(1, requests.lock().unwrap().len());
+        }
+    }
+
+    mod mutations {
+        use super::*;
+
+        #[test]
+        fn reports_errors() {
+            let (url, _) = serve(vec![(
+                200,
+                r#"{"data": {"mergePullRequest": null}, "errors": [{"type": "UNPROCESSABLE", "message": "Pull request is not mergeable"}]}"#,
+            )]);
+
+            assert_eq!(
+                "GitHub returned errors: Pull request is not mergeable",
+                github(&url, &[])
+                    .merge_pull_request("PR_1", graphql::MergeMethod::Squash)
+                    .unwrap_err()
+                    .to_string()
+            )
+        }
+
+        #[test]
+        fn requests_review_from_a_team() {
+            let (url, requests) = serve(vec![
+                (
+                    200,
+                    r#"{"data": {"user": null, "node": {"baseRepository": {"owner": {"team": {"id": "T_1"}}}}}, "errors": [{"type": "NOT_FOUND", "message": "Could not resolve to a User with the login of 'core-team'."}]}"#,
+                ),
+                (
+                    200,
+                    r#"{"data": {"requestReviews": {"pullRequest": {"id": "PR_1"}}}}"#,
+                ),
+            ]);
+
+            github(&url, &[])
+                .request_review("PR_1", "core-team")
+                .unwrap();
+            assert_eq!(2, requests.lock().unwrap().len());
+        }
+
+        #[test]
+        fn unknown_reviewer() {
+            let (url, _) = serve(vec![(
+                200,
+                r#"{"data": {"user": null, "node": {"baseRepository": {"owner": {}}}}}"#,
+            )]);
+
+            assert_eq!(
+                "could not find a user or team named nobody",
+                github(&url, &[])
+                    .request_review("PR_1", "nobody")
+                    .unwrap_err()
+                    .to_string()
+            )
+        }
+    }
+
+    /// Just enough of a PR for `graphql::PullRequest`, with a check run and
+    /// a rollup to ask about.
+    fn pr_node(number: u64) -> Value {
+        json!({
+            "number": number,
+            "title": "Title",
+            "url": format!("https://github.com/org/repo/pull/{number}"),
+            "headRef": { "name": "branch" },
+            "updatedAt": "2024-01-01T00:00:00Z",
+            "isDraft": false,
+            "commits": {
+                "nodes": [{
+                    "commit": {
+                        "id": format!("C_{number}"),
+                        "checkSuites": {
+                            "nodes": [{
+                                "id": format!("CS_{number}"),
+                                "checkRuns": {
+                                    "nodes": [{
+                                        "id": format!("CR_{number}"),
+                                        "name": "build",
+                                        "url": "https://github.com/org/repo/runs/1",
+                                        "conclusion": "SUCCESS",
+                                    }],
+                                },
+                            }],
+                        },
+                        "statusCheckRollup": { "state": "SUCCESS" },
+                    },
+                }],
+            },
+        })
+    }
+
+    fn pull_requests_page(nodes: &[Value], start_cursor: Option<&str>) -> String {
+        json!({
+            "data": {
+                "viewer": {
+                    "pullRequests": {
+                        "pageInfo": {
+                            "hasPreviousPage": start_cursor.is_some(),
+                            "startCursor": start_cursor,
+                        },
+                        "nodes": nodes,
+                    },
+                },
+            },
+        })
+        .to_string()
+    }
+
+    fn required_checks(name: &str) -> Value {
+        json!({
+            "statusCheckRollup": {
+                "contexts": {
+                    "nodes": [{ "name": name, "isRequired": true }],
+                },
+            },
+        })
+    }
+
+    mod pagination {
+        use super::*;
+
+        fn numbers(prs: &[Result<graphql::PullRequest>]) -> Vec<u64> {
+            prs.iter().map(|pr| pr.as_ref().unwrap().number).collect()
+        }
+
+        fn search_page(nodes: &[Value], end_cursor: Option<&str>) -> String {
+            json!({
+                "data": {
+                    "search": {
+                        "pageInfo": {
+                            "hasNextPage": end_cursor.is_some(),
+                            "endCursor": end_cursor,
+                        },
+                        "nodes": nodes,
+                    },
+                },
+            })
+            .to_string()
+        }
+
+        fn run(name: &str) -> Value {
+            json!({ "name": name, "url": "https://github.com/org/repo/runs/1", "conclusion": "SUCCESS" })
+        }
+
+        #[test]
+        fn pull_requests_earlier_pages_first() {
+            let (url, requests) = serve(vec![
+                (
+                    200,
+                    pull_requests_page(&[pr_node(3), pr_node(4)], Some("page-2")),
+                ),
+                (200, pull_requests_page(&[pr_node(1), pr_node(2)], None)),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            assert_eq!(vec![1, 2, 3, 4], numbers(&prs));
+            assert_eq!(
+                json!("page-2"),
+                requests.lock().unwrap()[1]["variables"]["before"]
+            );
+        }
+
+        #[test]
+        fn pull_requests_limit() {
+            let (url, requests) = serve(vec![
+                (
+                    200,
+                    pull_requests_page(&[pr_node(3), pr_node(4)], Some("page-2")),
+                ),
+                (200, pull_requests_page(&[pr_node(2)], Some("page-3"))),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(3).unwrap();
+
+            assert_eq!(vec![2, 3, 4], numbers(&prs));
+
+            let requests = requests.lock().unwrap();
+            assert_eq!(json!(3), requests[0]["variables"]["pageSize"]);
+            assert_eq!(json!(1), requests[1]["variables"]["pageSize"]);
+            // the third request is for required checks, not more PRs
+            assert!(requests[2]["query"]
+                .as_str()
+                .unwrap()
+                .starts_with("query RequiredChecks"));
+        }
+
+        #[test]
+        fn search_in_order() {
+            let (url, requests) = serve(vec![
+                (200, search_page(&[pr_node(1), pr_node(2)], Some("page-2"))),
+                (200, search_page(&[pr_node(3), pr_node(4)], None)),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &[]).search("is:pr", 10).unwrap();
+
+            assert_eq!(vec![1, 2, 3, 4], numbers(&prs));
+            assert_eq!(
+                json!("page-2"),
+                requests.lock().unwrap()[1]["variables"]["after"]
+            );
+        }
+
+        #[test]
+        fn search_limit() {
+            let (url, requests) = serve(vec![
+                (200, search_page(&[pr_node(1), pr_node(2)], Some("page-2"))),
+                (200, search_page(&[pr_node(3)], Some("page-3"))),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &[]).search("is:pr", 3).unwrap();
+
+            assert_eq!(vec![1, 2, 3], numbers(&prs));
+            assert_eq!(
+                json!(1),
+                requests.lock().unwrap()[1]["variables"]["pageSize"]
+            );
+        }
+
+        #[test]
+        fn search_skips_other_results() {
+            let (url, _) = serve(vec![
+                (200, search_page(&[pr_node(1), json!({}), pr_node(2)], None)),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &[]).search("label:bug", 10).unwrap();
+
+            assert_eq!(vec![1, 2], numbers(&prs));
+        }
+
+        #[test]
+        fn check_suites_and_runs() {
+            let mut node = pr_node(1);
+            node["commits"]["nodes"][0]["commit"]["checkSuites"]["pageInfo"] =
+                json!({ "hasNextPage": true, "endCursor": "suites-2" });
+
+            let (url, requests) = serve(vec![
+                (200, pull_requests_page(&[node], None)),
+                (
+                    200,
+                    json!({
+                        "data": {
+                            "node": {
+                                "checkSuites": {
+                                    "nodes": [{
+                                        "id": "CS_2",
+                                        "checkRuns": {
+                                            "pageInfo": { "hasNextPage": true, "endCursor": "runs-2" },
+                                            "nodes": [run("lint")],
+                                        },
+                                    }],
+                                },
+                            },
+                        },
+                    })
+                    .to_string(),
+                ),
+                (
+                    200,
+                    json!({ "data": { "node": { "checkRuns": { "nodes": [run("test")] } } } })
+                        .to_string(),
+                ),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            assert_eq!(
+                vec!["build", "lint", "test"],
+                prs[0]
+                    .as_ref()
+                    .unwrap()
+                    .check_runs()
+                    .map(|run| run.name.as_str())
+                    .collect::<Vec<_>>()
+            );
+
+            let requests = requests.lock().unwrap();
+            assert_eq!(
+                json!({ "id": "C_1", "after": "suites-2" }),
+                requests[1]["variables"]
+            );
+            assert_eq!(
+                json!({ "id": "CS_2", "after": "runs-2" }),
+                requests[2]["variables"]
+            );
+        }
+
+        fn threads(node: &mut Value, end_cursor: &str) {
+            node["id"] = json!("PR_1");
+            node["reviewThreads"] = json!({
+                "pageInfo": { "hasNextPage": true, "endCursor": end_cursor },
+                "nodes": [{ "isResolved": true }],
+            });
+        }
+
+        fn unresolved(pr: &graphql::PullRequest) -> usize {
+            pr.review_threads.as_ref().map_or(0, |threads| {
+                threads
+                    .nodes
+                    .iter()
+                    .filter(|thread| !thread.is_resolved)
+                    .count()
+            })
+        }
+
+        #[test]
+        fn review_threads() {
+            let mut node = pr_node(1);
+            threads(&mut node, "threads-2");
+
+            let (url, requests) = serve(vec![
+                (200, pull_requests_page(&[node], None)),
+                (
+                    200,
+                    json!({
+                        "data": {
+                            "node": {
+                                "reviewThreads": { "nodes": [{ "isResolved": false }] },
+                            },
+                        },
+                    })
+                    .to_string(),
+                ),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            assert_eq!(1, unresolved(prs[0].as_ref().unwrap()));
+            assert_eq!(
+                json!({ "id": "PR_1", "after": "threads-2" }),
+                requests.lock().unwrap()[1]["variables"]
+            );
+        }
+
+        #[test]
+        fn review_threads_failing() {
+            let mut node = pr_node(1);
+            threads(&mut node, "threads-2");
+
+            let (url, _) = serve(vec![
+                (200, pull_requests_page(&[node], None)),
+                (500, "{}".to_string()),
+                (200, json!({ "data": {} }).to_string()),
+            ]);
+
+            let prs = github(&url, &["--retries", "0"]).pull_requests(10).unwrap();
+
+            assert_eq!(0, unresolved(prs[0].as_ref().unwrap()));
+        }
+    }
+
+    mod required_checks {
+        use super::*;
+
+        fn contexts(pr: &graphql::PullRequest) -> Vec<&str> {
+            pr.last_commit()
+                .and_then(|commit| commit.status_check_rollup.as_ref())
+                .and_then(|rollup| rollup.contexts.as_ref())
+                .map(|contexts| contexts.nodes.iter().filter_map(|c| c.name()).collect())
+                .unwrap_or_default()
+        }
+
+        fn has_contexts(pr: &graphql::PullRequest) -> bool {
+            pr.last_commit()
+                .and_then(|commit| commit.status_check_rollup.as_ref())
+                .is_some_and(|rollup| rollup.contexts.is_some())
+        }
+
+        #[test]
+        fn batched() {
+            let (url, requests) = serve(vec![
+                (200, pull_requests_page(&[pr_node(1), pr_node(2)], None)),
+                (
+                    200,
+                    json!({
+                        "data": {
+                            "item0": required_checks("build"),
+                            "item1": required_checks("lint"),
+                        },
+                    })
+                    .to_string(),
+                ),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            assert_eq!(vec!["build"], contexts(prs[0].as_ref().unwrap()));
+            assert_eq!(vec!["lint"], contexts(prs[1].as_ref().unwrap()));
+
+            let requests = requests.lock().unwrap();
+            assert!(requests[1]["query"]
+                .as_str()
+                .unwrap()
+                .contains("item1: node(id: $id1)"));
+            assert_eq!(json!(2), requests[1]["variables"]["number1"]);
+        }
+
+        #[test]
+        fn follows_pages() {
+            let mut first_page = required_checks("build");
+            first_page["statusCheckRollup"]["contexts"]["pageInfo"] =
+                json!({ "hasNextPage": true, "endCursor": "ctx" });
+
+            let (url, requests) = serve(vec![
+                (200, pull_requests_page(&[pr_node(1)], None)),
+                (200, json!({ "data": { "item0": first_page } }).to_string()),
+                (
+                    200,
+                    json!({ "data": { "item0": required_checks("lint") } }).to_string(),
+                ),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            assert_eq!(vec!["build", "lint"], contexts(prs[0].as_ref().unwrap()));
+            assert_eq!(
+                json!("ctx"),
+                requests.lock().unwrap()[2]["variables"]["after0"]
+            );
+        }
+
+        #[test]
+        fn one_pr_failing() {
+            let (url, _) = serve(vec![
+                (200, pull_requests_page(&[pr_node(1), pr_node(2)], None)),
+                (
+                    200,
+                    json!({
+                        "data": {
+                            "item0": required_checks("build"),
+                            "item1": null,
+                        },
+                        "errors": [{
+                            "type": "FORBIDDEN",
+                            "path": ["item1"],
+                            "message": "Resource not accessible by integration",
+                        }],
+                    })
+                    .to_string(),
+                ),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            assert_eq!(vec!["build"], contexts(prs[0].as_ref().unwrap()));
+            assert!(!has_contexts(prs[1].as_ref().unwrap()));
+        }
+
+        #[test]
+        fn whole_batch_failing() {
+            let (url, _) = serve(vec![
+                (200, pull_requests_page(&[pr_node(1), pr_node(2)], None)),
+                (500, "{}".to_string()),
+            ]);
+
+            let prs = github(&url, &["--retries", "0"]).pull_requests(10).unwrap();
+
+            assert_eq!(2, prs.len());
+            for pr in &prs {
+                assert!(!has_contexts(pr.as_ref().unwrap()));
+            }
+        }
+
+        #[test]
+        fn later_page_failing() {
+            let mut first_page = required_checks("build");
+            first_page["statusCheckRollup"]["contexts"]["pageInfo"] =
+                json!({ "hasNextPage": true, "endCursor": "ctx" });
+
+            let (url, _) = serve(vec![
+                (200, pull_requests_page(&[pr_node(1)], None)),
+                (200, json!({ "data": { "item0": first_page } }).to_string()),
+                (500, "{}".to_string()),
+            ]);
+
+            let prs = github(&url, &["--retries", "0"]).pull_requests(10).unwrap();
+
+            assert!(!has_contexts(prs[0].as_ref().unwrap()));
+        }
+    }
+
+    mod failure_details {
+        use super::*;
+
+        fn failing_pr_node(number: u64) -> Value {
+            let mut node = pr_node(number);
+            node["commits"]["nodes"][0]["commit"]["checkSuites"]["nodes"][0]["checkRuns"]
+                ["nodes"][0]["conclusion"] = json!("FAILURE");
+            node
+        }
+
+        fn details(title: &str) -> Value {
+            json!({ "title": title, "summary": null, "annotations": { "nodes": [] } })
+        }
+
+        fn title(pr: &graphql::PullRequest) -> Option<&str> {
+            pr.check_runs().next()?.title.as_deref()
+        }
+
+        #[test]
+        fn batched() {
+            let (url, requests) = serve(vec![
+                (
+                    200,
+                    pull_requests_page(&[failing_pr_node(1), failing_pr_node(2)], None),
+                ),
+                (200, json!({ "data": {} }).to_string()),
+                (
+                    200,
+                    json!({
+                        "data": {
+                            "item0": details("2 tests failed"),
+                            "item1": details("lint failed"),
+                        },
+                    })
+                    .to_string(),
+                ),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            assert_eq!(Some("2 tests failed"), title(prs[0].as_ref().unwrap()));
+            assert_eq!(Some("lint failed"), title(prs[1].as_ref().unwrap()));
+
+            let requests = requests.lock().unwrap();
+            assert_eq!(3, requests.len());
+            assert_eq!(json!("CR_2"), requests[2]["variables"]["id1"]);
+        }
+
+        #[test]
+        fn one_pr_failing() {
+            let (url, _) = serve(vec![
+                (
+                    200,
+                    pull_requests_page(&[failing_pr_node(1), failing_pr_node(2)], None),
+                ),
+                (200, json!({ "data": {} }).to_string()),
+                (
+                    200,
+                    json!({
+                        "data": { "item0": null, "item1": details("lint failed") },
+                        "errors": [{
+                            "type": "FORBIDDEN",
+                            "path": ["item0"],
+                            "message": "Resource not accessible by integration",
+                        }],
+                    })
+                    .to_string(),
+                ),
+            ]);
+
+            let prs = github(&url, &[]).pull_requests(10).unwrap();
+
+            let first = prs[0].as_ref().unwrap();
+            assert_eq!(1, first.number);
+            assert_eq!(None, title(first));
+            assert!(first.check_runs().next().unwrap().annotations.is_none());
+            assert_eq!(Some("lint failed"), title(prs[1].as_ref().unwrap()));
+        }
+
+        #[test]
+        fn whole_batch_failing() {
+            let (url, _) = serve(vec![
+                (200, pull_requests_page(&[failing_pr_node(1)], None)),
+                (200, json!({ "data": {} }).to_string()),
+                (500, "{}".to_string()),
+            ]);
+
+            let prs = github(&url, &["--retries", "0"]).pull_requests(10).unwrap();
+
+            assert_eq!(None, title(prs[0].as_ref().unwrap()));
+        }
+    }
+
+    mod host {
+        use super::*;
+
+        #[test]
+        fn url_and_token() {
+            assert_eq!(
+                Host {
+                    api_url: "https://github.example.com/api/graphql".into(),
+                    api_token: "ghp_AAAA".into(),
+                },
+                Host::from_str("https://github.example.com/api/graphql=ghp_AAAA").unwrap()
+            )
+        }
+
+        #[test]
+        fn equals_in_url() {
+            assert_eq!(
+                "https://github.example.com/api/graphql?a=b",
+                Host::from_str("https://github.example.com/api/graphql?a=b=ghp_AAAA")
+                    .unwrap()
+                    .api_url
+            )
+        }
+
+        #[test]
+        fn no_equals() {
+            assert_eq!(
+                "I couldn't find a `=` between the API URL and token in `nope`",
+                Host::from_str("nope").unwrap_err().to_string()
+            )
+        }
+    }
+
+    mod web_url {
+        use super::*;
+
+        #[test]
+        fn github_dot_com() {
+            assert_eq!(
+                "https://github.com",
+                GitHub::new("https://api.github.com/graphql", "", &options(), None)
+                    .unwrap()
+                    .web_url()
+            )
+        }
+
+        #[test]
+        fn enterprise_server() {
+            assert_eq!(
+                "https://github.example.com",
+                GitHub::new(
+                    "https://github.example.com/api/graphql",
+                    "",
+                    &options(),
+                    None
+                )
+                .unwrap()
+                .web_url()
+            )
+        }
+    }
+
+    mod rest_url {
+        use super::*;
+
+        #[test]
+        fn github_dot_com() {
+            assert_eq!(
+                "https://api.github.com",
+                GitHub::new("https://api.github.com/graphql", "", &options(), None)
+                    .unwrap()
+                    .rest_url()
+            )
+        }
+
+        #[test]
+        fn enterprise_server() {
+            assert_eq!(
+                "https://github.example.com/api/v3",
+                GitHub::new(
+                    "https://github.example.com/api/graphql",
+                    "",
+                    &options(),
+                    None
+                )
+                .unwrap()
+                .rest_url()
+            )
+        }
+    }
+
+    mod rate_limit {
+        use super::*;
+        use reqwest::header::HeaderValue;
+
+        fn headers() -> HeaderMap {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-ratelimit-limit", HeaderValue::from_static("5000"));
+            headers.insert("x-ratelimit-remaining", HeaderValue::from_static("4990"));
+            headers.insert("x-ratelimit-reset", HeaderValue::from_static("1700000000"));
+            headers
+        }
+
+        #[test]
+        fn from_headers() {
+            assert_eq!(
+                Some(graphql::RateLimit {
+                    cost: None,
+                    limit: Some(5000),
+                    remaining: 4990,
+                    reset_at: DateTime::from_timestamp(1700000000, 0).unwrap(),
+                }),
+                rate_limit_from_headers(&headers())
+            )
+        }
+
+        #[test]
+        fn body_takes_precedence_over_headers() {
+            let github =
+                GitHub::new("https://api.github.com/graphql", "", &options(), None).unwrap();
+
+            github.observe_rate_limit(
+                &headers(),
+                Some(&json!({
+                    "data": {
+                        "rateLimit": {
+                            "cost": 2,
+                            "limit": 5000,
+                            "remaining": 4988,
+                            "resetAt": "2023-11-14T22:13:20Z",
+                        }
+                    }
+                })),
+            );
+
+            let rate_limit = github.rate_limit().unwrap();
+            assert_eq!(Some(2), rate_limit.cost);
+            assert_eq!(4988, rate_limit.remaining);
+        }
+
+        #[test]
+        fn backs_off_until_reset() {
+            let github = GitHub::new(
+                // nothing should be listening here, and we shouldn't try
+                "http://127.0.0.1:9/graphql",
+                "",
+                &options(),
+                Some(graphql::RateLimit {
+                    cost: Some(1),
+                    limit: Some(5000),
+                    remaining: 50,
+                    reset_at: Utc::now() + TimeDelta::minutes(10),
+                }),
+            )
+            .unwrap();
+
+            let err = github.query::<Value>("query { viewer { login } }", json!({}));
+
+            assert!(matches!(
+                err.unwrap_err().downcast_ref::<Error>(),
+                Some(Error::RateLimited { .. })
+            ))
+        }
+    }
+
+    mod error {
+        use super::*;
+        use reqwest::header::HeaderValue;
+
+        #[test]
+        fn unauthorized() {
+            assert!(matches!(
+                Error::from_response(
+                    StatusCode::UNAUTHORIZED,
+                    &HeaderMap::new(),
+                    r#"{"message": "Bad credentials"}"#,
+                    "https://github.com"
+                ),
+                Error::BadCredentials { tokens_url } if tokens_url == "https://github.com/settings/tokens"
+            ))
+        }
+
+        #[test]
+        fn sso_required() {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "x-github-sso",
+                HeaderValue::from_static("required; url=https://github.com/orgs/acme/sso?x=1"),
+            );
+
+            assert!(matches!(
+                Error::from_response(StatusCode::FORBIDDEN, &headers, "", "https://github.com"),
+                Error::SsoRequired { authorize_url: Some(url), .. } if url == "https://github.com/orgs/acme/sso?x=1"
+            ))
+        }
+
+        #[test]
+        fn rate_limited() {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-ratelimit-remaining", HeaderValue::from_static("0"));
+            headers.insert("x-ratelimit-reset", HeaderValue::from_static("1700000000"));
+
+            assert!(matches!(
+                Error::from_response(StatusCode::FORBIDDEN, &headers, "", "https://github.com"),
+                Error::RateLimited { reset_at: Some(reset_at) } if reset_at.timestamp() == 1700000000
+            ))
+        }
+
+        #[test]
+        fn other_forbidden() {
+            assert!(matches!(
+                Error::from_response(
+                    StatusCode::FORBIDDEN,
+                    &HeaderMap::new(),
+                    r#"{"message": "Nope"}"#,
+                    "https://github.com"
+                ),
+                Error::Http { message, .. } if message == "Nope"
+            ))
+        }
+
+        #[test]
+        fn bad_gateway() {
+            assert!(matches!(
+                Error::from_response(
+                    StatusCode::BAD_GATEWAY,
+                    &HeaderMap::new(),
+                    "<html>oh no</html>",
+                    "https://github.com"
+                ),
+                Error::Http { status: StatusCode::BAD_GATEWAY, message } if message == "Bad Gateway"
+            ))
+        }
+
+        #[test]
+        fn secondary_rate_limit() {
+            let mut headers = HeaderMap::new();
+            headers.insert("retry-after", HeaderValue::from_static("60"));
+
+            assert!(matches!(
+                Error::from_response(
+                    StatusCode::FORBIDDEN,
+                    &headers,
+                    r#"{"message": "You have exceeded a secondary rate limit."}"#,
+                    "https://github.com"
+                ),
+                Error::RateLimited { reset_at: Some(reset_at) } if reset_at > Utc::now()
+            ))
+        }
+
+        #[test]
+        fn graphql_rate_limited() {
+            assert!(matches!(
+                Error::from_graphql_errors(
+                    &[json!({"type": "RATE_LIMITED", "message": "API rate limit exceeded"})],
+                    &HeaderMap::new(),
+                    "https://github.com"
+                ),
+                Error::RateLimited { .. }
+            ))
+        }
+
+        #[test]
+        fn graphql_other() {
+            assert_eq!(
+                "GitHub returned errors: one; two",
+                Error::from_graphql_errors(
+                    &[json!({"message": "one"}), json!({"message": "two"})],
+                    &HeaderMap::new(),
+                    "https://github.com"
+                )
+                .to_string()
+            )
+        }
+    }
+}
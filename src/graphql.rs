@@ -0,0 +1,426 @@
+//! Typed versions of the nodes we ask for in the `.graphql` files. These
+//! mirror the queries field-for-field, so if you add something to a query,
+//! add it here too. They serialize back to the same shape, which is what we
+//! keep in the cache.
+
+use crate::check_status::CheckStatus;
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Deserialize a value, reporting the exact path to the field that failed.
+pub fn from_value<T: DeserializeOwned>(value: &Value) -> Result<T> {
+    Ok(serde_path_to_error::deserialize(value)?)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection<T> {
+    #[serde(default)]
+    pub page_info: PageInfo,
+    pub nodes: Vec<T>,
+}
+
+impl<T> Connection<T> {
+    /// Add the nodes from the next page of a connection onto the ones we
+    /// already have, and take its page info so we know where to go next.
+    pub fn extend(&mut self, next_page: Connection<T>) {
+        self.nodes.extend(next_page.nodes);
+        self.page_info = next_page.page_info;
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    #[serde(default)]
+    pub has_next_page: bool,
+    pub end_cursor: Option<String>,
+    #[serde(default)]
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+}
+
+/// `rateLimit` at the top of each query. We also fill this in from the
+/// `x-ratelimit-*` headers, which don't say what a query cost.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub cost: Option<u64>,
+    pub limit: Option<u64>,
+    pub remaining: u64,
+    pub reset_at: DateTime<Utc>,
+}
+
+/// `PullRequestFields` in `pull_request_fields.graphql`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequest {
+    pub id: Option<String>,
+    pub number: u64,
+    pub title: String,
+    pub url: String,
+    pub head_ref: Ref,
+    pub updated_at: DateTime<FixedOffset>,
+    pub is_draft: bool,
+    pub viewer_did_author: Option<bool>,
+    pub commits: Connection<CommitNode>,
+    pub mergeable: Option<MergeableState>,
+    pub merge_state_status: Option<MergeStateStatus>,
+    pub merge_queue_entry: Option<MergeQueueEntry>,
+    pub is_merge_queue_enabled: Option<bool>,
+    pub auto_merge_request: Option<AutoMergeRequest>,
+    pub viewer_can_enable_auto_merge: Option<bool>,
+    pub base_repository: Option<Repository>,
+    pub review_requests: Option<Connection<ReviewRequest>>,
+    pub review_decision: Option<ReviewDecision>,
+    pub latest_opinionated_reviews: Option<Connection<Review>>,
+    pub latest_reviews: Option<Connection<Review>>,
+    pub review_threads: Option<Connection<ReviewThread>>,
+}
+
+impl PullRequest {
+    /// Load a PR node, saying which PR it was if anything goes wrong.
+    pub fn from_value(value: &Value) -> Result<Self> {
+        from_value(value).with_context(|| match value.get("number").and_then(Value::as_u64) {
+            Some(number) => format!("could not load PR #{number}"),
+            None => "could not load a PR".to_string(),
+        })
+    }
+
+    pub fn last_commit(&self) -> Option<&Commit> {
+        self.commits.nodes.last().map(|node| &node.commit)
+    }
+
+    pub fn last_commit_mut(&mut self) -> Option<&mut Commit> {
+        self.commits.nodes.last_mut().map(|node| &mut node.commit)
+    }
+
+    /// Every check run on the PR's commits
+    pub fn check_runs(&self) -> impl Iterator<Item = &CheckRun> {
+        self.commits
+            .nodes
+            .iter()
+            .filter_map(|node| node.commit.check_suites.as_ref())
+            .flat_map(|suites| &suites.nodes)
+            .flat_map(|suite| &suite.check_runs.nodes)
+    }
+
+    pub fn check_runs_mut(&mut self) -> impl Iterator<Item = &mut CheckRun> {
+        self.commits
+            .nodes
+            .iter_mut()
+            .filter_map(|node| node.commit.check_suites.as_mut())
+            .flat_map(|suites| &mut suites.nodes)
+            .flat_map(|suite| &mut suite.check_runs.nodes)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Ref {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CommitNode {
+    pub commit: Commit,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Commit {
+    pub id: Option<String>,
+    pub oid: Option<String>,
+    pub status: Option<Status>,
+    pub check_suites: Option<Connection<CheckSuite>>,
+    pub status_check_rollup: Option<StatusCheckRollup>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Status {
+    pub contexts: Vec<StatusContext>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusContext {
+    pub context: String,
+    pub state: CheckStatus,
+    pub target_url: Option<String>,
+}
+
+/// The PR queries only ask for `state`. `contexts` comes from
+/// `required_checks.graphql`, which needs the PR number.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StatusCheckRollup {
+    pub state: CheckStatus,
+    pub contexts: Option<Connection<RollupContext>>,
+}
+
+/// Check runs have a `name`; status contexts have a `context`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollupContext {
+    pub name: Option<String>,
+    pub context: Option<String>,
+    pub is_required: bool,
+}
+
+impl RollupContext {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref().or(self.context.as_deref())
+    }
+}
+
+/// `CheckSuiteConnectionFields` in `check_suite_fields.graphql`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckSuite {
+    pub id: Option<String>,
+    pub check_runs: Connection<CheckRun>,
+}
+
+/// `CheckRunConnectionFields` in `check_run_fields.graphql`. `title`,
+/// `summary`, and `annotations` come from `check_run_details.graphql`, which
+/// we only ask for when a check fails.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckRun {
+    pub id: Option<String>,
+    pub name: String,
+    pub url: String,
+    pub conclusion: Option<CheckStatus>,
+    pub status: Option<CheckRunStatus>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    pub annotations: Option<Connection<CheckAnnotation>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckAnnotation {
+    pub path: String,
+    pub location: CheckAnnotationSpan,
+    pub message: String,
+    pub blob_url: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CheckAnnotationSpan {
+    pub start: CheckAnnotationPosition,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CheckAnnotationPosition {
+    pub line: u64,
+}
+
+/// Where a check run is up to. Once it's `Completed`, it has a conclusion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CheckRunStatus {
+    Completed,
+    InProgress,
+    Pending,
+    Queued,
+    Requested,
+    Waiting,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MergeableState {
+    Mergeable,
+    Conflicting,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Why a PR can (or can't) be merged right now. GitHub only reports the
+/// most pressing reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MergeStateStatus {
+    /// The head ref is out of date with the base branch
+    Behind,
+    /// Something other than CI (for example, required reviews) is blocking
+    /// the merge
+    Blocked,
+    Clean,
+    /// There are merge conflicts
+    Dirty,
+    Draft,
+    HasHooks,
+    Unstable,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MergeQueueEntry {
+    pub position: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoMergeRequest {
+    pub enabled_at: Option<DateTime<Utc>>,
+}
+
+/// Which merge methods the base repository allows
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Repository {
+    pub merge_commit_allowed: bool,
+    pub squash_merge_allowed: bool,
+    pub rebase_merge_allowed: bool,
+}
+
+impl Repository {
+    pub fn merge_methods(&self) -> Vec<MergeMethod> {
+        let mut methods = Vec::new();
+        if self.squash_merge_allowed {
+            methods.push(MergeMethod::Squash);
+        }
+        if self.merge_commit_allowed {
+            methods.push(MergeMethod::Merge);
+        }
+        if self.rebase_merge_allowed {
+            methods.push(MergeMethod::Rebase);
+        }
+        methods
+    }
+}
+
+/// `PullRequestMergeMethod`, which we also take on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MergeMethod {
+    Squash,
+    Merge,
+    Rebase,
+}
+
+impl MergeMethod {
+    /// How we spell this on the command line
+    pub fn as_arg(&self) -> &'static str {
+        match self {
+            MergeMethod::Squash => "squash",
+            MergeMethod::Merge => "merge",
+            MergeMethod::Rebase => "rebase",
+        }
+    }
+
+    /// What GitHub calls this in its UI
+    pub fn label(&self) -> &'static str {
+        match self {
+            MergeMethod::Squash => "Squash and merge",
+            MergeMethod::Merge => "Create a merge commit",
+            MergeMethod::Rebase => "Rebase and merge",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewRequest {
+    pub requested_reviewer: Option<RequestedReviewer>,
+}
+
+/// Users, bots, and mannequins have a `login`; teams have a `slug`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RequestedReviewer {
+    pub login: Option<String>,
+    pub slug: Option<String>,
+}
+
+impl RequestedReviewer {
+    pub fn name(self) -> Option<String> {
+        self.login.or(self.slug)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReviewDecision {
+    Approved,
+    ChangesRequested,
+    ReviewRequired,
+    #[serde(other)]
+    Unknown,
+}
+
+/// We ask for less about `latestOpinionatedReviews` than `latestReviews`,
+/// so the optional fields are only there for the latter.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Review {
+    pub state: ReviewState,
+    pub author: Option<Actor>,
+    pub submitted_at: Option<DateTime<FixedOffset>>,
+    pub url: Option<String>,
+    pub commit: Option<CommitOid>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CommitOid {
+    pub oid: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReviewState {
+    Approved,
+    ChangesRequested,
+    Commented,
+    Dismissed,
+    Pending,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewThread {
+    pub is_resolved: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Actor {
+    pub login: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_path_and_number() {
+        let mut value: Value =
+            serde_json::from_str(include_str!("test_fixtures/pr_failing.json")).unwrap();
+        value["commits"]["nodes"][0]["commit"]["statusCheckRollup"]["state"] = "NOPE".into();
+
+        assert_eq!(
+            "could not load PR #1: commits.nodes[0].commit.statusCheckRollup.state: got unexpected value NOPE as a CheckStatus",
+            format!("{:#}", PullRequest::from_value(&value).unwrap_err())
+        )
+    }
+
+    #[test]
+    fn reports_missing_fields() {
+        let mut value: Value =
+            serde_json::from_str(include_str!("test_fixtures/pr_failing.json")).unwrap();
+        value.as_object_mut().unwrap().remove("title");
+
+        assert_eq!(
+            "could not load PR #1: missing field `title`",
+            format!("{:#}", PullRequest::from_value(&value).unwrap_err())
+        )
+    }
+}
@@ -1,16 +1,24 @@
+mod cache;
 mod check_status;
-mod navigate_value;
+mod clipboard;
+mod command;
+mod github;
+mod graphql;
 mod pull_request;
+mod section;
 mod xbar;
 
-use crate::navigate_value::NavigateValue;
+use crate::cache::{ActionFailure, Cache};
+use crate::command::Command;
+use crate::github::{GitHub, Host};
 use crate::pull_request::PullRequest;
-use anyhow::{bail, Context, Result};
-use chrono::{Duration, Local};
+use crate::section::Section;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local, Utc};
 use clap::Parser;
-use reqwest::blocking::Client;
-use reqwest::header;
-use serde_json::{json, Value};
+use std::path::PathBuf;
+
+const REVIEW_REQUESTED_QUERY: &str = "is:open is:pr review-requested:@me";
 
 #[derive(Debug, Parser)]
 #[clap(about, author)]
@@ -18,26 +26,182 @@ pub struct Config {
     /// A GitHub access token, created with the `repo` and `read:user` scopes.
     ///
     /// You can make one of these st https://github.com/settings/tokens
+    ///
+    /// Menu actions don't pass this along, so to use them, set
+    /// GITHUB_API_TOKEN instead of giving the token as an argument.
     #[clap(env = "GITHUB_API_TOKEN")]
-    github_api_token: String,
+    github_api_token: Option<String>,
+
+    /// The GraphQL endpoint to use with `github_api_token`. For GitHub
+    /// Enterprise Server, this is usually `https://HOSTNAME/api/graphql`.
+    #[clap(
+        long,
+        env = "GITHUB_API_URL",
+        default_value = "https://api.github.com/graphql"
+    )]
+    api_url: String,
+
+    /// Also load PRs from another GitHub instance, with its own token. Format
+    /// is `API_URL=TOKEN`. May be given multiple times; results from every
+    /// host are merged into one menu. GITHUB_HOSTS takes several of these,
+    /// separated by spaces, and is where menu actions look for their tokens.
+    #[clap(long("host"), env = "GITHUB_HOSTS", value_delimiter = ' ', action = clap::ArgAction::Append)]
+    hosts: Vec<Host>,
 
     /// Ignore PRs updated last before this many days ago
     #[clap(long, env = "SINCE")]
     since: Option<i64>,
 
+    /// Stop loading open PRs after this many. The most recently created PRs
+    /// are loaded first.
+    #[clap(long, env = "MAX_PULL_REQUESTS", default_value = "100")]
+    max_pull_requests: usize,
+
+    /// Also show open PRs where you are a requested reviewer, in their own
+    /// section below your PRs.
+    #[clap(long, env = "SHOW_REVIEW_REQUESTS")]
+    show_review_requests: bool,
+
+    /// Add a section to the dropdown listing the PRs matching a GitHub
+    /// search query. Format is `NAME=QUERY`, for example `Release
+    /// train=org:acme is:pr is:open label:release-blocker`. May be given
+    /// multiple times.
+    #[clap(long("section"), action = clap::ArgAction::Append)]
+    sections: Vec<Section>,
+
+    /// Where to keep the last PRs we loaded, to show when GitHub can't be
+    /// reached. Defaults to a file in your user cache directory.
+    #[clap(long, env = "CACHE_FILE")]
+    cache_file: Option<PathBuf>,
+
+    /// A shell command for the menu's copy actions to run, which reads the
+    /// text from stdin. By default we use whichever of pbcopy, wl-copy,
+    /// xclip, or xsel we can find.
+    #[clap(long, env = "COPY_COMMAND")]
+    copy_command: Option<String>,
+
+    #[clap(flatten)]
+    github: github::Options,
+
     #[clap(flatten)]
     emoji: xbar::Emoji,
+
+    /// Make a change instead of printing the menu. The menu's actions use
+    /// these; you shouldn't need to run them yourself.
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// The GraphQL endpoint of the host a menu action is about. This is
+    /// separate from `--api-url` so that actions never send the main token
+    /// anywhere but the main host.
+    #[clap(long, hide = true)]
+    action_api_url: Option<String>,
+}
+
+impl Config {
+    /// The token for `api_url`: the main one if it's the main host, or the
+    /// one from `--host` (or GITHUB_HOSTS) if it's one of those. Menu actions
+    /// only get the API URL, so this is how they find their token.
+    fn token_for(&self, api_url: &str) -> Result<&str> {
+        if api_url == self.api_url {
+            return self.github_api_token.as_deref().with_context(|| {
+                format!("I need an API token for {api_url}. Set GITHUB_API_TOKEN where xbar can see it.")
+            });
+        }
+
+        self.hosts
+            .iter()
+            .find(|host| host.api_url == api_url)
+            .map(|host| host.api_token.as_str())
+            .with_context(|| {
+                format!("I need an API token for {api_url}. Add it to GITHUB_HOSTS where xbar can see it.")
+            })
+    }
 }
 
 fn main() {
     env_logger::Builder::from_env("XBAR_PR_STATUS_LOG").init();
 
     if let Err(err) = try_main() {
-        println!("{err:?}");
+        print!("{}", error_menu(&err));
         std::process::exit(1);
     }
 }
 
+/// Explain what went wrong in the menubar, with links to fix it where we can.
+fn error_menu(err: &anyhow::Error) -> String {
+    let mut lines: Vec<String> = Vec::new();
+
+    match err.downcast_ref::<github::Error>() {
+        Some(github::Error::BadCredentials { tokens_url }) => {
+            lines.push("🔑".to_string());
+            lines.push("---".to_string());
+            lines.push("GitHub did not accept your API token".to_string());
+            lines.push("It may have expired or been revoked.".to_string());
+            lines.push(format!("Create a new token | href={tokens_url}"));
+            lines.push("It needs the repo and read:user scopes.".to_string());
+        }
+        Some(github::Error::SsoRequired {
+            authorize_url,
+            tokens_url,
+        }) => {
+            lines.push("🔐".to_string());
+            lines.push("---".to_string());
+            lines.push("Your API token needs SSO authorization".to_string());
+            if let Some(authorize_url) = authorize_url {
+                lines.push(format!("Authorize this token | href={authorize_url}"));
+            }
+            lines.push(format!("Configure SSO for your tokens | href={tokens_url}"));
+        }
+        Some(github::Error::RateLimited { reset_at }) => {
+            lines.push("⏳".to_string());
+            lines.push("---".to_string());
+            lines.push("GitHub's API rate limit is used up".to_string());
+            if let Some(reset_at) = reset_at {
+                lines.push(format!(
+                    "It resets at {}",
+                    reset_at.with_timezone(&Local).format("%H:%M")
+                ));
+            }
+            lines.push("About rate limits | href=https://docs.github.com/en/graphql/overview/rate-limits-and-query-limits-for-the-graphql-api".to_string());
+        }
+        Some(github::Error::Network(_)) => {
+            lines.push("📡".to_string());
+            lines.push("---".to_string());
+            lines.push("Could not reach GitHub".to_string());
+            lines.push("Check your network or VPN connection.".to_string());
+            lines.push("GitHub status | href=https://www.githubstatus.com".to_string());
+        }
+        Some(github::Error::Http { status, .. }) => {
+            lines.push("💥".to_string());
+            lines.push("---".to_string());
+            lines.push(format!("GitHub responded with {status}"));
+            lines.push("GitHub status | href=https://www.githubstatus.com".to_string());
+        }
+        Some(github::Error::GraphQL(messages)) => {
+            lines.push("💥".to_string());
+            lines.push("---".to_string());
+            lines.push("GitHub could not answer our query".to_string());
+            for message in messages {
+                lines.push(message.replace('|', "\\|"));
+            }
+        }
+        None => {
+            lines.push("💥".to_string());
+            lines.push("---".to_string());
+            lines.push("Something went wrong".to_string());
+        }
+    }
+
+    lines.push("---".to_string());
+    lines.push("Details".to_string());
+    for cause in err.chain() {
+        lines.push(format!("-- {}", cause.to_string().replace('|', "\\|")));
+    }
+
+    format!("{}\n", lines.join("\n"))
+}
+
 fn try_main() -> Result<()> {
     let config = Config::parse();
     let cutoff_opt = config
@@ -45,23 +209,308 @@ fn try_main() -> Result<()> {
         .and_then(Duration::try_days)
         .map(|days| Local::now() - days);
 
-    let prs = fetch(&config.github_api_token).context("could not fetch pull requests")?;
+    let cache_path = config.cache_file.clone().or_else(Cache::default_path);
+
+    if let Some(command) = &config.command {
+        let api_url = config.action_api_url.as_deref().unwrap_or(&config.api_url);
+        let result = command.run(|| {
+            GitHub::new(
+                api_url,
+                config.token_for(api_url)?,
+                &config.github,
+                Cache::load(cache_path.clone()).rate_limit(api_url),
+            )
+        });
+
+        // nobody sees what we print here, so save the error for the menu
+        if let Err(err) = &result {
+            let mut cache = Cache::load(cache_path);
+            cache.set_action_failure(err);
+            if let Err(err) = cache.save() {
+                log::warn!("{err:?}");
+            }
+        }
+
+        return result;
+    }
+
+    let mut cache = Cache::load(cache_path);
+
+    let hosts: Vec<Host> = std::iter::once(Host {
+        api_url: config.api_url.clone(),
+        api_token: config
+            .github_api_token
+            .clone()
+            .context("I need a GitHub API token, either as an argument or in GITHUB_API_TOKEN")?,
+    })
+    .chain(config.hosts.iter().cloned())
+    .collect();
+
+    let mut clients = Vec::with_capacity(hosts.len());
+    for host in &hosts {
+        clients.push(GitHub::new(
+            &host.api_url,
+            &host.api_token,
+            &config.github,
+            cache.rate_limit(&host.api_url),
+        )?);
+    }
+
+    let callbacks = callbacks(&clients, config.copy_command.as_deref());
+
+    let mut failures: Vec<anyhow::Error> = Vec::new();
+    let mut failed_hosts: Vec<(String, anyhow::Error)> = Vec::new();
+    let authored = load_pull_requests(
+        fetch_from_all(
+            &clients,
+            &mut cache,
+            "viewer",
+            "pull requests",
+            &mut failed_hosts,
+            |github| github.pull_requests(config.max_pull_requests),
+        ),
+        &cache,
+        cutoff_opt,
+        &mut failures,
+    );
 
-    let mut top_line: Vec<&str> = Vec::new();
+    let mut top_line: Vec<String> = Vec::new();
     let mut menu_lines: Vec<String> = Vec::new();
 
-    if let Ok(errors) = prs.get_array("/errors") {
-        for error in errors {
-            log::error!("{error:#?}");
+    for pr in &authored {
+        top_line.push(config.emoji.for_status(pr.status()).to_string());
+        menu_lines.push(pr.to_xbar_menu(&config.emoji, callback_for(&callbacks, pr)));
+    }
+
+    if config.show_review_requests {
+        let review_requested = load_pull_requests(
+            fetch_from_all(
+                &clients,
+                &mut cache,
+                REVIEW_REQUESTED_QUERY,
+                "pull requests awaiting your review",
+                &mut failed_hosts,
+                |github| github.search(REVIEW_REQUESTED_QUERY, config.max_pull_requests),
+            ),
+            &cache,
+            cutoff_opt,
+            &mut failures,
+        );
+
+        if !review_requested.is_empty() {
+            top_line.push(format!(
+                " {}{}",
+                config.emoji.review_requested(),
+                review_requested.len()
+            ));
+
+            push_section(
+                &mut menu_lines,
+                "Review requested",
+                &review_requested,
+                &config,
+                &callbacks,
+            );
+        }
+    }
+
+    for section in &config.sections {
+        let prs = load_pull_requests(
+            fetch_from_all(
+                &clients,
+                &mut cache,
+                &section.query,
+                &format!("pull requests for {}", section.name),
+                &mut failed_hosts,
+                |github| github.search(&section.query, config.max_pull_requests),
+            ),
+            &cache,
+            cutoff_opt,
+            &mut failures,
+        );
+
+        push_section(&mut menu_lines, &section.name, &prs, &config, &callbacks);
+    }
+
+    // with nothing to show, explain what went wrong instead
+    if failed_hosts.len() == clients.len() {
+        return Err(failed_hosts.remove(0).1);
+    }
+
+    push_failures(
+        &mut menu_lines,
+        "host",
+        failed_hosts.iter().map(|(_, err)| err),
+    );
+    push_failures(&mut menu_lines, "PR", failures.iter());
+    push_debug(&mut menu_lines, &clients);
+
+    for github in &clients {
+        if let Some(rate_limit) = github.rate_limit() {
+            cache.set_rate_limit(github.api_url(), rate_limit);
+        }
+    }
+
+    if let Some(failure) = cache.take_action_failure() {
+        top_line.insert(0, "⚠️ ".to_string());
+        menu_lines.splice(0..0, action_failure_lines(&failure));
+    }
+
+    if let Err(err) = cache.save() {
+        log::warn!("{err:?}");
+    }
+
+    if let Some(stale) = cache.stale() {
+        top_line.insert(0, format!("{} ", config.emoji.stale()));
+        menu_lines.insert(0, "---".to_string());
+        menu_lines.insert(
+            0,
+            format!("-- {} | color=gray", stale.reason.replace('|', "\\|")),
+        );
+        menu_lines.insert(
+            0,
+            format!(
+                "Last updated {} ago | color=gray",
+                xbar::format_duration(Utc::now() - stale.since)
+            ),
+        );
+    }
+
+    print!(
+        "{}\n---\n{}\n",
+        top_line.join("").trim_start(),
+        menu_lines.join("\n")
+    );
+
+    Ok(())
+}
+
+/// Run the same fetch against every configured host and merge the results.
+/// If a host can't be reached right now, we use what we got from it last
+/// time instead. If that doesn't work either, the host goes in
+/// `failed_hosts` and we leave it out of this and any later fetches, so one
+/// broken host doesn't take the whole menu down with it.
+fn fetch_from_all<F>(
+    clients: &[GitHub],
+    cache: &mut Cache,
+    key: &str,
+    what: &str,
+    failed_hosts: &mut Vec<(String, anyhow::Error)>,
+    fetch: F,
+) -> Vec<Result<graphql::PullRequest>>
+where
+    F: Fn(&GitHub) -> Result<Vec<Result<graphql::PullRequest>>>,
+{
+    let mut out = Vec::new();
+
+    for github in clients {
+        let api_url = github.api_url();
+        if failed_hosts.iter().any(|(failed, _)| failed == api_url) {
+            continue;
         }
+
+        let cache_key = format!("{api_url} {key}");
+
+        let result = match fetch(github) {
+            Ok(prs) => cache.store(&cache_key, &prs).map(|()| prs),
+            Err(err)
+                if err
+                    .downcast_ref::<github::Error>()
+                    .is_some_and(github::Error::is_transient) =>
+            {
+                cache.fall_back(&cache_key, err)
+            }
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(mut prs) => out.append(&mut prs),
+            Err(err) => {
+                let err = err.context(format!("could not fetch {what} from {api_url}"));
+                log::warn!("{err:?}");
+                failed_hosts.push((api_url.to_string(), err));
+            }
+        }
+    }
+
+    out
+}
+
+fn push_section(
+    menu_lines: &mut Vec<String>,
+    heading: &str,
+    prs: &[PullRequest],
+    config: &Config,
+    callbacks: &[(String, xbar::Callback)],
+) {
+    menu_lines.push("---".to_string());
+    menu_lines.push(heading.replace('|', "\\|"));
+
+    if prs.is_empty() {
+        menu_lines.push("No open pull requests".to_string());
     }
 
-    for pr_value in prs.get_array("/data/viewer/pullRequests/nodes")? {
-        let pr = match PullRequest::try_from(pr_value).context("could not load a Pull Request") {
+    for pr in prs {
+        menu_lines.push(pr.to_xbar_menu(&config.emoji, callback_for(callbacks, pr)));
+    }
+}
+
+/// A way to run subcommands against each host, keyed by the host's web URL
+/// so we can tell which one a PR came from. If we can't tell where this
+/// program is, the menu just won't have any actions.
+fn callbacks(clients: &[GitHub], copy_command: Option<&str>) -> Vec<(String, xbar::Callback)> {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe.to_string_lossy().to_string(),
+        Err(err) => {
+            log::warn!("could not find this program to run menu actions: {err}");
+            return Vec::new();
+        }
+    };
+
+    // xbar has no way to escape a quote in a parameter
+    if exe.contains('"') {
+        log::warn!("can't run {exe} from menu actions because of the quote in its path");
+        return Vec::new();
+    }
+
+    clients
+        .iter()
+        .map(|github| {
+            (
+                github.web_url(),
+                xbar::Callback::new(&exe, github.api_url(), copy_command),
+            )
+        })
+        .collect()
+}
+
+fn callback_for<'a>(
+    callbacks: &'a [(String, xbar::Callback)],
+    pr: &PullRequest,
+) -> Option<&'a xbar::Callback> {
+    callbacks
+        .iter()
+        .find(|(web_url, _)| pr.url().starts_with(&format!("{web_url}/")))
+        .map(|(_, callback)| callback)
+}
+
+/// Turn PR nodes into `PullRequest`s, setting aside any that we couldn't
+/// load in `failures` instead of giving up on the whole menu.
+fn load_pull_requests(
+    nodes: Vec<Result<graphql::PullRequest>>,
+    cache: &Cache,
+    cutoff_opt: Option<DateTime<Local>>,
+    failures: &mut Vec<anyhow::Error>,
+) -> Vec<PullRequest> {
+    let mut out = Vec::with_capacity(nodes.len());
+
+    for node in nodes {
+        let mut pr = match node.and_then(PullRequest::try_from) {
             Ok(pr) => pr,
             Err(err) => {
-                log::debug!("{:#?}", pr_value);
-                return Err(err).context("could not load a Pull Request");
+                log::error!("{err:?}");
+                failures.push(err);
+                continue;
             }
         };
 
@@ -69,51 +518,346 @@ fn try_main() -> Result<()> {
             continue;
         }
 
-        top_line.push(config.emoji.for_status(pr.status()));
-        menu_lines.push(pr.to_xbar_menu(&config.emoji));
+        pr.set_usual_durations(cache);
+
+        out.push(pr);
     }
 
-    print!("{}\n---\n{}\n", top_line.join(""), menu_lines.join("\n"));
+    out
+}
+
+/// Say what went wrong with the last menu action, at the top of the menu.
+fn action_failure_lines(failure: &ActionFailure) -> Vec<String> {
+    let mut lines = vec![format!(
+        "⚠️ Your last action failed {} ago",
+        xbar::format_duration(Utc::now() - failure.failed_at)
+    )];
 
-    Ok(())
+    for message in &failure.messages {
+        lines.push(format!("-- {}", message.replace('|', "\\|")));
+    }
+
+    lines.push("---".to_string());
+    lines
 }
 
-fn fetch(api_token: &str) -> Result<Value> {
-    let client = Client::builder()
-        .user_agent(concat!(
-            env!("CARGO_PKG_NAME"),
-            "/",
-            env!("CARGO_PKG_VERSION")
-        ))
-        .build()
-        .context("could not build the HTTP client")?;
-
-    let response = client
-        .post("https://api.github.com/graphql")
-        .header(
-            header::AUTHORIZATION,
-            header::HeaderValue::from_str(&format!("Bearer {api_token}"))
-                .context("could not create an Authorization header from the specified token")?,
-        )
-        .json(&json!({ "query": include_str!("pull_requests.graphql") }))
-        .send()
-        .context("could not request data from GitHub's API")?;
-
-    let body: Value = response.json().context("could not read JSON body")?;
-
-    log::debug!("body: {body:#?}");
-
-    if let Some(value) = body.pointer("errors") {
-        match value {
-            Value::Null => (),
-            Value::Array(errs) => {
-                for err in errs {
-                    log::error!("{}", err);
+fn push_failures<'a>(
+    menu_lines: &mut Vec<String>,
+    what: &str,
+    failures: impl ExactSizeIterator<Item = &'a anyhow::Error>,
+) {
+    if failures.len() == 0 {
+        return;
+    }
+
+    menu_lines.push("---".to_string());
+    menu_lines.push(format!(
+        "⚠️ {} {what}{} could not be loaded",
+        failures.len(),
+        if failures.len() == 1 { "" } else { "s" }
+    ));
+
+    for failure in failures {
+        let mut chain = failure.chain();
+        if let Some(top) = chain.next() {
+            menu_lines.push(format!("-- {}", top.to_string().replace('|', "\\|")));
+        }
+        for cause in chain {
+            menu_lines.push(format!("---- {}", cause.to_string().replace('|', "\\|")));
+        }
+    }
+}
+
+/// Details about how we're talking to GitHub, tucked away at the bottom.
+fn push_debug(menu_lines: &mut Vec<String>, clients: &[GitHub]) {
+    menu_lines.push("---".to_string());
+    menu_lines.push("Debug".to_string());
+
+    for github in clients {
+        menu_lines.push(format!("-- {}", github.api_url()));
+
+        match github.rate_limit() {
+            Some(rate_limit) => {
+                menu_lines.push(format!(
+                    "---- {} of {} API points left",
+                    rate_limit.remaining,
+                    rate_limit
+                        .limit
+                        .map_or_else(|| "?".to_string(), |limit| limit.to_string())
+                ));
+                menu_lines.push(format!(
+                    "---- Resets at {}",
+                    rate_limit.reset_at.with_timezone(&Local).format("%H:%M")
+                ));
+                if let Some(cost) = rate_limit.cost {
+                    menu_lines.push(format!("---- Last query cost {cost}"));
                 }
             }
-            _ => bail!("errors was not an array"),
+            None => menu_lines.push("---- No rate limit information yet".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod token_for {
+        use super::*;
+
+        fn config() -> Config {
+            Config::parse_from([
+                "test",
+                "MAIN_TOKEN",
+                "--host",
+                "https://github.example.com/api/graphql=GHES_TOKEN",
+            ])
+        }
+
+        #[test]
+        fn main_host() {
+            assert_eq!(
+                "MAIN_TOKEN",
+                config()
+                    .token_for("https://api.github.com/graphql")
+                    .unwrap()
+            )
+        }
+
+        #[test]
+        fn additional_host() {
+            assert_eq!(
+                "GHES_TOKEN",
+                config()
+                    .token_for("https://github.example.com/api/graphql")
+                    .unwrap()
+            )
+        }
+
+        #[test]
+        fn unknown_host() {
+            assert_eq!(
+                "I need an API token for https://evil.example.com/api/graphql. Add it to GITHUB_HOSTS where xbar can see it.",
+                config()
+                    .token_for("https://evil.example.com/api/graphql")
+                    .unwrap_err()
+                    .to_string()
+            )
+        }
+    }
+
+    mod error_menu {
+        use super::*;
+
+        #[test]
+        fn bad_credentials_with_context() {
+            let err = anyhow::Error::new(github::Error::BadCredentials {
+                tokens_url: "https://github.com/settings/tokens".to_string(),
+            })
+            .context("could not fetch pull requests");
+
+            let menu = error_menu(&err);
+            let lines: Vec<&str> = menu.lines().collect();
+
+            assert_eq!("🔑", lines[0]);
+            assert!(lines.contains(&"Create a new token | href=https://github.com/settings/tokens"));
+            assert!(lines.contains(&"-- could not fetch pull requests"));
+        }
+
+        #[test]
+        fn sso_required_with_context() {
+            let err = anyhow::Error::new(github::Error::SsoRequired {
+                authorize_url: Some(
+                    "https://github.com/orgs/acme/sso?authorization_request=1".to_string(),
+                ),
+                tokens_url: "https://github.com/settings/tokens".to_string(),
+            })
+            .context("could not fetch pull requests");
+
+            let menu = error_menu(&err);
+            let lines: Vec<&str> = menu.lines().collect();
+
+            assert_eq!("🔐", lines[0]);
+            assert!(lines.contains(
+                &"Authorize this token | href=https://github.com/orgs/acme/sso?authorization_request=1"
+            ));
+        }
+
+        #[test]
+        fn escapes_pipes() {
+            let err = anyhow::Error::new(github::Error::GraphQL(vec![
+                "unexpected `|` in query".to_string()
+            ]))
+            .context("could not fetch pull requests for a|b");
+
+            let menu = error_menu(&err);
+            let lines: Vec<&str> = menu.lines().collect();
+
+            assert!(lines.contains(&"unexpected `\\|` in query"));
+            assert!(lines.contains(&"-- could not fetch pull requests for a\\|b"));
+        }
+    }
+
+    mod load_pull_requests {
+        use super::*;
+
+        fn node(number: u64, title: &str) -> Result<graphql::PullRequest> {
+            let mut node: serde_json::Value =
+                serde_json::from_str(include_str!("test_fixtures/pr_approved.json")).unwrap();
+            node["number"] = number.into();
+            node["title"] = title.into();
+            node["url"] = format!("https://github.com/org/repo/pull/{number}").into();
+            graphql::PullRequest::from_value(&node)
+        }
+
+        #[test]
+        fn skips_malformed_nodes() {
+            let mut failures = Vec::new();
+            let malformed = graphql::PullRequest::from_value(&serde_json::json!({ "number": 2 }));
+
+            let prs = load_pull_requests(
+                vec![node(1, "First"), malformed, node(3, "Third")],
+                &Cache::load(None),
+                None,
+                &mut failures,
+            );
+
+            assert_eq!(1, failures.len());
+
+            let mut menu_lines = Vec::new();
+            push_section(
+                &mut menu_lines,
+                "Mine",
+                &prs,
+                &Config::parse_from(["test"]),
+                &[],
+            );
+            let menu = menu_lines.join("\n");
+            assert!(menu.contains("First"));
+            assert!(menu.contains("Third"));
+        }
+    }
+
+    mod push_failures {
+        use super::*;
+        use anyhow::anyhow;
+
+        #[test]
+        fn one() {
+            let mut menu_lines = Vec::new();
+
+            push_failures(&mut menu_lines, "PR", [anyhow!("broken")].iter());
+
+            assert_eq!(
+                vec!["---", "⚠️ 1 PR could not be loaded", "-- broken"],
+                menu_lines
+            );
+        }
+
+        #[test]
+        fn several() {
+            let mut menu_lines = Vec::new();
+
+            push_failures(
+                &mut menu_lines,
+                "PR",
+                [anyhow!("broken"), anyhow!("also broken")].iter(),
+            );
+
+            assert_eq!("⚠️ 2 PRs could not be loaded", menu_lines[1]);
+        }
+
+        #[test]
+        fn none() {
+            let mut menu_lines = Vec::new();
+
+            push_failures(&mut menu_lines, "host", std::iter::empty());
+
+            assert!(menu_lines.is_empty());
+        }
+
+        #[test]
+        fn error_chain() {
+            let mut menu_lines = Vec::new();
+            let err = anyhow!("missing field `a|b`").context("could not load PR #2");
+
+            push_failures(&mut menu_lines, "PR", [err].iter());
+
+            assert_eq!(
+                vec![
+                    "---",
+                    "⚠️ 1 PR could not be loaded",
+                    "-- could not load PR #2",
+                    "---- missing field `a\\|b`",
+                ],
+                menu_lines
+            );
         }
     }
 
-    Ok(body)
+    mod fetch_from_all {
+        use super::*;
+        use anyhow::anyhow;
+
+        const BROKEN: &str = "https://github.example.com/api/graphql";
+
+        fn clients() -> Vec<GitHub> {
+            let config = Config::parse_from(["test"]);
+            ["https://api.github.com/graphql", BROKEN]
+                .into_iter()
+                .map(|api_url| GitHub::new(api_url, "TOKEN", &config.github, None).unwrap())
+                .collect()
+        }
+
+        fn fetch(github: &GitHub) -> Result<Vec<Result<graphql::PullRequest>>> {
+            if github.api_url() == BROKEN {
+                return Err(anyhow!("not found"));
+            }
+            Ok(vec![graphql::PullRequest::from_value(
+                &serde_json::from_str(include_str!("test_fixtures/pr_approved.json")).unwrap(),
+            )])
+        }
+
+        #[test]
+        fn keeps_the_other_hosts() {
+            let mut failed_hosts = Vec::new();
+
+            let prs = fetch_from_all(
+                &clients(),
+                &mut Cache::load(None),
+                "viewer",
+                "pull requests",
+                &mut failed_hosts,
+                fetch,
+            );
+
+            assert_eq!(1, prs.len());
+            assert_eq!(1, failed_hosts.len());
+            assert_eq!(BROKEN, failed_hosts[0].0);
+            assert_eq!(
+                format!("could not fetch pull requests from {BROKEN}"),
+                failed_hosts[0].1.to_string()
+            );
+        }
+
+        #[test]
+        fn skips_hosts_that_already_failed() {
+            let clients = clients();
+            let mut cache = Cache::load(None);
+            let mut failed_hosts = Vec::new();
+
+            for key in ["viewer", "review requested"] {
+                fetch_from_all(
+                    &clients,
+                    &mut cache,
+                    key,
+                    "pull requests",
+                    &mut failed_hosts,
+                    fetch,
+                );
+            }
+
+            assert_eq!(1, failed_hosts.len());
+        }
+    }
 }
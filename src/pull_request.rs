@@ -1,135 +1,325 @@
+use crate::cache::Cache;
 use crate::check_status::CheckStatus;
-use crate::navigate_value::NavigateValue;
+use crate::graphql;
 use crate::xbar;
-use anyhow::{anyhow, bail, Context, Result};
-use chrono::{DateTime, FixedOffset};
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
 use serde_json::Value;
 
+/// A check is slow if it takes this many times longer than usual.
+const SLOW_CHECK_FACTOR: i32 = 2;
+
 #[derive(Debug)]
 pub struct PullRequest {
+    id: Option<String>,
     number: u64,
     title: String,
     head_ref: String,
     url: String,
     pub updated_at: DateTime<FixedOffset>,
     is_draft: bool,
-    reviewer: Option<String>,
-    approved: bool,
+    /// Only the author gets actions that change what the PR is, like
+    /// merging it or turning it back into a draft
+    viewer_did_author: bool,
+    reviewers: Vec<String>,
+    review_status: ReviewStatus,
+    changes_requested_by: Vec<String>,
+    reviews: Vec<Review>,
+    unresolved_threads: usize,
+    mergeable: Option<graphql::MergeableState>,
+    merge_state_status: Option<graphql::MergeStateStatus>,
     queue_position: Option<u64>,
+    merge_queue_enabled: bool,
+    auto_merge_enabled: bool,
+    can_enable_auto_merge: bool,
+    /// Which ways the base repository lets us merge
+    merge_methods: Vec<graphql::MergeMethod>,
     overall_status: Option<CheckStatus>,
     checks: Vec<Check>,
 }
 
-impl PullRequest {
-    fn overall_status_from_commit(commit: &Value) -> Result<Option<CheckStatus>> {
-        match commit.pointer("/statusCheckRollup/state") {
-            Some(state) => Ok(Some(
-                state
-                    .as_str()
-                    .ok_or_else(|| anyhow!("state was not a string"))?
-                    .try_into()
-                    .context("could not load status from overall state")?,
-            )),
-            None => Ok(None),
-        }
-    }
-
-    fn checks_from_commit(commit: &Value) -> Result<Vec<Check>> {
-        let mut out = Vec::new();
-
-        if let Some(contexts) = commit.pointer("/status/contexts") {
-            for context in contexts
-                .as_array()
-                .ok_or_else(|| anyhow!("contexts was not an array"))?
-            {
-                out.push(
-                    Check::from_context(context)
-                        .context("could not load a context in the contexts array")?,
-                );
-            }
-        }
+/// Where a PR stands with its reviewers, regardless of CI.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Approved,
+    ChangesRequested,
+    /// People have reviewed, but only left comments
+    Commented,
+    /// The base branch requires a review that hasn't happened yet
+    ReviewRequired,
+    NotReviewed,
+}
 
-        for suite in commit.get_array("/checkSuites/nodes")? {
-            for run in suite.get_array("/checkRuns/nodes")? {
-                out.push(
-                    Check::from_check_run(run)
-                        .context("could not a load a check run in the check suites/runs array")?,
-                )
+impl ReviewStatus {
+    fn from_reviews(
+        decision: Option<graphql::ReviewDecision>,
+        opinionated: &[graphql::Review],
+        commented: bool,
+    ) -> Self {
+        let has_opinion = |state| opinionated.iter().any(|review| review.state == state);
+
+        match decision {
+            Some(graphql::ReviewDecision::Approved) => ReviewStatus::Approved,
+            Some(graphql::ReviewDecision::ChangesRequested) => ReviewStatus::ChangesRequested,
+            // GitHub only makes a decision when the base branch requires
+            // reviews. Otherwise, we go by the reviews themselves.
+            None if has_opinion(graphql::ReviewState::ChangesRequested) => {
+                ReviewStatus::ChangesRequested
             }
+            None if has_opinion(graphql::ReviewState::Approved) => ReviewStatus::Approved,
+            _ if commented => ReviewStatus::Commented,
+            Some(graphql::ReviewDecision::ReviewRequired) => ReviewStatus::ReviewRequired,
+            _ => ReviewStatus::NotReviewed,
         }
+    }
+}
 
-        Ok(out)
+impl PullRequest {
+    fn approved(&self) -> bool {
+        self.review_status == ReviewStatus::Approved
     }
 
-    fn approved_from_pr(pr: &Value) -> Result<bool> {
-        match pr.pointer("/latestOpinionatedReviews/nodes/0/state") {
-            Some(state) => Ok(state
-                .as_str()
-                .ok_or_else(|| anyhow!("approval state was not a string"))?
-                == "APPROVED"),
-            None => Ok(false),
-        }
+    fn has_conflicts(&self) -> bool {
+        self.mergeable == Some(graphql::MergeableState::Conflicting)
+            || self.merge_state_status == Some(graphql::MergeStateStatus::Dirty)
     }
 
-    fn queue_position_from_pr(pr: &Value) -> Result<Option<u64>> {
-        pr.get_nullable_u64("/mergeQueueEntry/position")
+    /// Whether every check that hasn't finished is stuck waiting for a
+    /// runner.
+    fn waiting_for_runners(&self) -> bool {
+        let mut pending = self
+            .checks
+            .iter()
+            .filter(|check| check.status.is_pending())
+            .peekable();
+
+        pending.peek().is_some() && pending.all(|check| check.status == CheckStatus::Queued)
     }
 
     pub fn status(&self) -> xbar::Status {
         match &self.overall_status {
+            None if self.has_conflicts() => xbar::Status::Conflicting,
             None => xbar::Status::Unknown,
             Some(CheckStatus::Success) => {
                 if let Some(position) = self.queue_position {
                     xbar::Status::Queued(position)
-                } else if self.approved {
-                    xbar::Status::SuccessAndApproved
-                } else if let Some(reviewer) = &self.reviewer {
-                    xbar::Status::SuccessAwaitingApproval(reviewer.to_string())
+                } else if self.has_conflicts() {
+                    xbar::Status::Conflicting
+                } else if self.approved() {
+                    // once a PR is approved, anything else stopping it from
+                    // merging is worth knowing about
+                    match self.merge_state_status {
+                        _ if self.unresolved_threads > 0 => xbar::Status::OpenThreads,
+                        Some(graphql::MergeStateStatus::Behind) => xbar::Status::BehindBase,
+                        Some(graphql::MergeStateStatus::Blocked) => xbar::Status::Blocked,
+                        _ => xbar::Status::SuccessAndApproved,
+                    }
+                } else if self.review_status == ReviewStatus::ChangesRequested {
+                    xbar::Status::ChangesRequested
+                } else if !self.reviewers.is_empty() {
+                    xbar::Status::SuccessAwaitingApproval(self.reviewers.clone())
                 } else if self.is_draft {
                     xbar::Status::Draft
+                } else if self.merge_state_status == Some(graphql::MergeStateStatus::Behind) {
+                    xbar::Status::BehindBase
                 } else {
                     xbar::Status::Success
                 }
             }
+            Some(CheckStatus::Pending) if self.waiting_for_runners() => xbar::Status::CheckQueued,
             Some(other) => other.into(),
         }
     }
 
-    pub fn to_xbar_menu(&self, emoji: &xbar::Emoji) -> String {
+    /// Fill in how long each check usually takes, from the runs we've seen
+    /// pass before.
+    pub fn set_usual_durations(&mut self, cache: &Cache) {
+        for check in self.checks.iter_mut() {
+            if let Some(url) = &check.url {
+                check.usual_duration = cache.usual_check_duration(url, &check.name);
+            }
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Menu items to merge the PR, enable auto-merge, or add it to the merge
+    /// queue, whichever make sense right now.
+    fn merge_actions(&self, id: &str, callback: &xbar::Callback) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        if self.queue_position.is_some() || self.is_draft || self.has_conflicts() {
+            return lines;
+        }
+
+        if self.auto_merge_enabled {
+            lines.push("-- Auto-merge is enabled | color=gray".to_string());
+            return lines;
+        }
+
+        let ready = self.status() == xbar::Status::SuccessAndApproved;
+
+        if self.merge_queue_enabled {
+            if ready {
+                lines.push(format!(
+                    "-- Add to merge queue | {}",
+                    callback.params(&["enqueue", "--pull-request", id])
+                ));
+            }
+            return lines;
+        }
+
+        if ready && !self.merge_methods.is_empty() {
+            lines.push("-- Merge".to_string());
+            for method in &self.merge_methods {
+                lines.push(format!(
+                    "---- {} | {}",
+                    method.label(),
+                    callback.params(&["merge", "--pull-request", id, "--method", method.as_arg()])
+                ));
+            }
+        }
+
+        // GitHub won't enable auto-merge on a PR it could merge right away
+        let failing = self
+            .overall_status
+            .as_ref()
+            .is_some_and(CheckStatus::is_failing);
+        if !ready && !failing && self.can_enable_auto_merge && !self.merge_methods.is_empty() {
+            lines.push("-- Enable auto-merge".to_string());
+            for method in &self.merge_methods {
+                lines.push(format!(
+                    "---- {} | {}",
+                    method.label(),
+                    callback.params(&[
+                        "auto-merge",
+                        "--pull-request",
+                        id,
+                        "--method",
+                        method.as_arg(),
+                    ])
+                ));
+            }
+        }
+
+        lines
+    }
+
+    /// `callback` is for actions that change the PR, if we can run them.
+    pub fn to_xbar_menu(&self, emoji: &xbar::Emoji, callback: Option<&xbar::Callback>) -> String {
         let mut out_lines: Vec<String> = Vec::new();
+        let mut title = self.title.replace('|', "\\|");
+        if self.unresolved_threads > 0 {
+            title.push_str(&format!(
+                " {}{}",
+                emoji.for_status(xbar::Status::OpenThreads),
+                self.unresolved_threads
+            ));
+        }
+
         out_lines.push(format!(
             "{} {} | href={}",
             emoji.for_status(self.status()),
-            self.title.replace('|', "\\|"),
+            title,
             self.url
         ));
 
-        out_lines.push(format!(
-            "-- Copy URL | shell=bash param1=-c param2=\"printf '%s' '{}' | pbcopy\"",
-            self.url
-        ));
+        if let Some(callback) = callback {
+            let number = format!("#{}", self.number);
+            let copyable = [
+                ("Copy URL".to_string(), &self.url),
+                (number.clone(), &number),
+                (self.head_ref.replace('|', "\\|"), &self.head_ref),
+            ];
+
+            for (label, text) in copyable {
+                if let Some(params) = callback.copy_params(text) {
+                    out_lines.push(format!("-- {label} | {params}"));
+                }
+            }
+        }
 
-        out_lines.push(format!(
-            "-- #{} | shell=bash param1=-c param2=\"printf '%s' '#{}' | pbcopy\"",
-            self.number, self.number
-        ));
+        if let (Some(id), Some(callback), true) = (&self.id, callback, self.viewer_did_author) {
+            out_lines.extend(self.merge_actions(id, callback));
+
+            let mut roster = emoji.reviewers().peekable();
+            if self.reviewers.is_empty() && roster.peek().is_some() {
+                out_lines.push("-- Request review from…".to_string());
+                for (reviewer, reviewer_emoji) in roster {
+                    out_lines.push(format!(
+                        "---- {reviewer_emoji} {reviewer} | {}",
+                        callback.params(&[
+                            "request-review",
+                            "--pull-request",
+                            id,
+                            "--reviewer",
+                            reviewer
+                        ])
+                    ));
+                }
+            }
 
-        out_lines.push(format!(
-            "-- {} | shell=bash param1=-c param2=\"printf '%s' '{}' | pbcopy\"",
-            self.head_ref, self.head_ref
-        ));
+            if self.is_draft {
+                out_lines.push(format!(
+                    "-- Ready for review | {}",
+                    callback.params(&["ready", "--pull-request", id])
+                ));
+            } else if self.queue_position.is_none() {
+                out_lines.push(format!(
+                    "-- Convert to draft | {}",
+                    callback.params(&["draft", "--pull-request", id])
+                ));
+            }
+        }
 
-        if let Some(reviewer) = &self.reviewer {
+        for reviewer in &self.reviewers {
             out_lines.push(format!("-- reviewer: {reviewer}"))
         }
 
-        for check in &self.checks {
+        if !self.changes_requested_by.is_empty() {
             out_lines.push(format!(
-                "-- {} {} | href={}",
-                emoji.for_status(xbar::Status::from(&check.status)),
-                check.name.replace('|', "\\|"),
-                check.url,
-            ))
+                "-- {} Changes requested",
+                emoji.for_status(xbar::Status::ChangesRequested)
+            ));
+            for login in &self.changes_requested_by {
+                out_lines.push(format!("---- {login}"));
+            }
+        }
+
+        if !self.reviews.is_empty() || !self.reviewers.is_empty() {
+            out_lines.push("-- Reviews".to_string());
+
+            for review in &self.reviews {
+                out_lines.push(format!("---- {}", review.to_xbar_line()));
+            }
+
+            for reviewer in &self.reviewers {
+                if !self
+                    .reviews
+                    .iter()
+                    .any(|review| &review.reviewer == reviewer)
+                {
+                    out_lines.push(format!("---- {reviewer}: review requested"));
+                }
+            }
+        }
+
+        let now = Utc::now();
+        for check in &self.checks {
+            out_lines.push(format!("-- {}", check.to_xbar_line(emoji, now)));
+            for line in check.failure_xbar_lines() {
+                out_lines.push(format!("---- {line}"));
+            }
+            if let (Some(check_suite), Some(callback), true) =
+                (&check.check_suite, callback, check.status.is_failing())
+            {
+                out_lines.push(format!(
+                    "---- Re-run failed jobs | {}",
+                    callback.params(&["rerun", "--check-suite", check_suite])
+                ));
+            }
         }
 
         out_lines.join("\n")
@@ -140,65 +330,410 @@ impl TryFrom<&Value> for PullRequest {
     type Error = anyhow::Error;
 
     fn try_from(pr: &Value) -> Result<PullRequest> {
+        graphql::PullRequest::from_value(pr)?.try_into()
+    }
+}
+
+impl TryFrom<graphql::PullRequest> for PullRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(pr: graphql::PullRequest) -> Result<PullRequest> {
         let commit = pr
-            .pointer("/commits/nodes/0/commit")
-            .ok_or_else(|| anyhow!("could not get the last commit"))?;
+            .commits
+            .nodes
+            .into_iter()
+            .last()
+            .map(|node| node.commit)
+            .with_context(|| format!("could not get the last commit of PR #{}", pr.number))?;
+
+        let reviewers = pr
+            .review_requests
+            .map(|requests| requests.nodes)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|request| request.requested_reviewer?.name())
+            .collect();
+
+        let opinionated = pr
+            .latest_opinionated_reviews
+            .map(|reviews| reviews.nodes)
+            .unwrap_or_default();
+
+        let latest_reviews = pr
+            .latest_reviews
+            .map(|reviews| reviews.nodes)
+            .unwrap_or_default();
+
+        let commented = latest_reviews
+            .iter()
+            .any(|review| review.state == graphql::ReviewState::Commented);
+
+        let review_status = ReviewStatus::from_reviews(pr.review_decision, &opinionated, commented);
+
+        let changes_requested_by = opinionated
+            .into_iter()
+            .filter(|review| review.state == graphql::ReviewState::ChangesRequested)
+            .filter_map(|review| review.author)
+            .map(|author| author.login)
+            .collect();
+
+        let reviews = latest_reviews
+            .into_iter()
+            .map(|review| Review {
+                stale: matches!(
+                    (&review.commit, &commit.oid),
+                    (Some(reviewed), Some(head)) if &reviewed.oid != head
+                ),
+                reviewer: review
+                    .author
+                    .map_or_else(|| "ghost".to_string(), |author| author.login),
+                state: review.state,
+                submitted_at: review.submitted_at,
+                url: review.url,
+            })
+            .collect();
+
+        let unresolved_threads = pr
+            .review_threads
+            .map(|threads| threads.nodes)
+            .unwrap_or_default()
+            .iter()
+            .filter(|thread| !thread.is_resolved)
+            .count();
+
+        let mut checks: Vec<Check> = Vec::new();
+        if let Some(status) = commit.status {
+            checks.extend(status.contexts.into_iter().map(Check::from));
+        }
+        for suite in commit
+            .check_suites
+            .into_iter()
+            .flat_map(|suites| suites.nodes)
+        {
+            checks.extend(suite.check_runs.nodes.into_iter().map(|run| Check {
+                check_suite: suite.id.clone(),
+                ..Check::from(run)
+            }));
+        }
 
-        let reviewer = match pr.get_str("/reviewRequests/nodes/0/requestedReviewer/login") {
-            Ok(reviewer) => Some(reviewer.into()),
-            Err(_) => None,
-        };
+        let mut overall_status = None;
+        if let Some(rollup) = commit.status_check_rollup {
+            let mut state = rollup.state;
+
+            // without branch protection nothing is required, but a PR with
+            // failing CI still shouldn't look mergeable. So we only treat
+            // checks as optional once we know at least one is required.
+            if let Some(contexts) = rollup
+                .contexts
+                .filter(|contexts| contexts.nodes.iter().any(|context| context.is_required))
+            {
+                for check in checks.iter_mut() {
+                    check.required = contexts.nodes.iter().any(|context| {
+                        context.is_required && context.name() == Some(check.name.as_str())
+                    });
+                }
+
+                // the rollup counts every check, but only required ones can
+                // stop the PR from merging
+                if state.is_failing()
+                    && !checks
+                        .iter()
+                        .any(|check| check.required && check.status.is_failing())
+                {
+                    let waiting_on = |pending: fn(&CheckStatus) -> bool| {
+                        checks
+                            .iter()
+                            .any(|check| check.required && pending(&check.status))
+                    };
+
+                    state = if waiting_on(CheckStatus::is_pending) {
+                        CheckStatus::Pending
+                    } else if waiting_on(|status| *status == CheckStatus::Expected) {
+                        CheckStatus::Expected
+                    } else {
+                        CheckStatus::Success
+                    };
+                }
+            }
+
+            overall_status = Some(state);
+        }
+        checks.sort_by_key(|check| !check.required);
 
         Ok(PullRequest {
-            number: pr.get_u64("/number")?,
-            title: pr.get_str("/title")?.into(),
-            url: pr.get_str("/url")?.into(),
-            head_ref: pr.get_str("/headRef/name")?.into(),
-            updated_at: DateTime::parse_from_rfc3339(pr.get_str("/updatedAt")?)
-                .context("updatedAt doesn't match the RFC3339 format")?,
-            is_draft: pr.get_bool("/isDraft")?,
-            reviewer,
-            approved: Self::approved_from_pr(pr)?,
-            queue_position: Self::queue_position_from_pr(pr)?,
-            overall_status: Self::overall_status_from_commit(commit)?,
-            checks: Self::checks_from_commit(commit)?,
+            id: pr.id,
+            number: pr.number,
+            title: pr.title,
+            url: pr.url,
+            head_ref: pr.head_ref.name,
+            updated_at: pr.updated_at,
+            is_draft: pr.is_draft,
+            viewer_did_author: pr.viewer_did_author.unwrap_or(false),
+            reviewers,
+            review_status,
+            changes_requested_by,
+            reviews,
+            unresolved_threads,
+            mergeable: pr.mergeable,
+            merge_state_status: pr.merge_state_status,
+            queue_position: pr.merge_queue_entry.map(|entry| entry.position),
+            merge_queue_enabled: pr.is_merge_queue_enabled.unwrap_or(false),
+            auto_merge_enabled: pr.auto_merge_request.is_some(),
+            can_enable_auto_merge: pr.viewer_can_enable_auto_merge.unwrap_or(false),
+            merge_methods: pr
+                .base_repository
+                .map(|repo| repo.merge_methods())
+                .unwrap_or_default(),
+            overall_status,
+            checks,
         })
     }
 }
 
+/// The latest review from one reviewer
+#[derive(Debug, PartialEq, Eq)]
+pub struct Review {
+    reviewer: String,
+    state: graphql::ReviewState,
+    submitted_at: Option<DateTime<FixedOffset>>,
+    url: Option<String>,
+    /// Whether commits have been pushed since this review
+    stale: bool,
+}
+
+impl Review {
+    fn to_xbar_line(&self) -> String {
+        let mut line = format!(
+            "{}: {}",
+            self.reviewer,
+            match self.state {
+                graphql::ReviewState::Approved => "approved",
+                graphql::ReviewState::ChangesRequested => "changes requested",
+                graphql::ReviewState::Commented => "commented",
+                graphql::ReviewState::Dismissed => "dismissed",
+                graphql::ReviewState::Pending => "pending",
+                graphql::ReviewState::Unknown => "reviewed",
+            }
+        );
+
+        if let Some(submitted_at) = self.submitted_at {
+            line.push_str(&format!(
+                " {} ago",
+                xbar::format_duration(Utc::now().fixed_offset() - submitted_at)
+            ));
+        }
+
+        if self.stale {
+            line.push_str(" (stale)");
+        }
+
+        if let Some(url) = &self.url {
+            line.push_str(&format!(" | href={url}"));
+        }
+
+        line
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Check {
     name: String,
     status: CheckStatus,
+    /// Statuses that haven't been reported yet don't have anywhere to go
+    url: Option<String>,
+    /// Whether branch protection requires this check to pass. We assume
+    /// it does until we hear otherwise.
+    required: bool,
+    /// Only check runs have timing information
+    started_at: Option<DateTime<Utc>>,
+    completed_at: Option<DateTime<Utc>>,
+    /// How long this check usually takes when it passes, if we've seen it
+    /// pass enough times to say
+    usual_duration: Option<TimeDelta>,
+    /// Why a check run failed, if it did
+    failure: Option<Failure>,
+    /// The node ID of the check suite a check run belongs to, so we can
+    /// re-run it
+    check_suite: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Failure {
+    title: Option<String>,
+    summary: Option<String>,
+    annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Annotation {
+    path: String,
+    line: u64,
+    message: String,
     url: String,
 }
 
+impl From<graphql::CheckAnnotation> for Annotation {
+    fn from(annotation: graphql::CheckAnnotation) -> Annotation {
+        let line = annotation.location.start.line;
+
+        Annotation {
+            path: annotation.path,
+            line,
+            message: annotation.message,
+            url: format!("{}#L{line}", annotation.blob_url),
+        }
+    }
+}
+
 impl Check {
-    fn from_context(context: &Value) -> Result<Check> {
-        Ok(Check {
-            name: context.get_str("/context")?.into(),
-            status: context
-                .get_str("/state")?
-                .try_into()
-                .context("could not load state from context")?,
-            url: context.get_str("/targetUrl")?.into(),
-        })
+    fn to_xbar_line(&self, emoji: &xbar::Emoji, now: DateTime<Utc>) -> String {
+        let mut line = format!(
+            "{} {}",
+            emoji.for_status(xbar::Status::from(&self.status)),
+            self.name.replace('|', "\\|"),
+        );
+
+        match (self.started_at, self.completed_at) {
+            (Some(started_at), Some(completed_at)) => line.push_str(&format!(
+                " ({})",
+                xbar::format_elapsed(completed_at - started_at)
+            )),
+            (Some(started_at), None) => line.push_str(&format!(
+                " — running for {}",
+                xbar::format_elapsed(now - started_at)
+            )),
+            _ => (),
+        }
+
+        if let Some(usual) = self.slower_than_usual(now) {
+            line.push_str(&format!(
+                " {} usually {}",
+                emoji.slow_check(),
+                xbar::format_elapsed(usual)
+            ));
+        }
+
+        if self.status == CheckStatus::Expected {
+            line.push_str(" (waiting for check to start)");
+        }
+
+        // an optional check failing shouldn't look like a blocker
+        let optional_failure = !self.required && self.status.is_failing();
+        if optional_failure {
+            line.push_str(" (optional)");
+        }
+
+        let mut params = Vec::new();
+        if let Some(url) = &self.url {
+            params.push(format!("href={url}"));
+        }
+        if optional_failure || self.status == CheckStatus::Expected {
+            params.push("color=gray".to_string());
+        }
+        if !params.is_empty() {
+            line.push_str(&format!(" | {}", params.join(" ")));
+        }
+
+        line
     }
 
-    fn from_check_run(run: &Value) -> Result<Check> {
-        Ok(Check {
-            name: run.get_str("/name")?.into(),
-            status: match run.get("conclusion") {
-                None => bail!("missing /conclusion in a check run"),
-                Some(Value::Null) => CheckStatus::Pending,
-                Some(conclusion) => conclusion
-                    .as_str()
-                    .ok_or_else(|| anyhow!("conclusion was not a string"))?
-                    .try_into()
-                    .context("could not convert conclusion to a status")?,
-            },
-            url: run.get_str("/url")?.into(),
-        })
+    /// The usual duration, if this check has taken (or has been running
+    /// for) much longer than that.
+    fn slower_than_usual(&self, now: DateTime<Utc>) -> Option<TimeDelta> {
+        let usual = self.usual_duration?;
+        let elapsed = self.completed_at.unwrap_or(now) - self.started_at?;
+
+        (elapsed > usual * SLOW_CHECK_FACTOR).then_some(usual)
+    }
+
+    /// Submenu lines explaining why the check failed.
+    fn failure_xbar_lines(&self) -> Vec<String> {
+        let failure = match &self.failure {
+            Some(failure) => failure,
+            None => return Vec::new(),
+        };
+
+        let mut lines = Vec::new();
+
+        if let Some(title) = &failure.title {
+            lines.push(title.replace('|', "\\|"));
+        }
+
+        // summaries are markdown and can go on for a while, so we only show
+        // the start
+        if let Some(summary) = failure
+            .summary
+            .as_deref()
+            .and_then(|summary| summary.lines().find(|line| !line.trim().is_empty()))
+        {
+            lines.push(format!(
+                "{} | color=gray",
+                summary.trim().replace('|', "\\|")
+            ));
+        }
+
+        for annotation in &failure.annotations {
+            lines.push(format!(
+                "{}:{}: {} | href={}",
+                annotation.path,
+                annotation.line,
+                annotation
+                    .message
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .replace('|', "\\|"),
+                annotation.url,
+            ));
+        }
+
+        lines
+    }
+}
+
+impl From<graphql::StatusContext> for Check {
+    fn from(context: graphql::StatusContext) -> Check {
+        Check {
+            name: context.context,
+            status: context.state,
+            url: context.target_url,
+            required: true,
+            started_at: None,
+            completed_at: None,
+            usual_duration: None,
+            failure: None,
+            check_suite: None,
+        }
+    }
+}
+
+impl From<graphql::CheckRun> for Check {
+    fn from(run: graphql::CheckRun) -> Check {
+        Check {
+            name: run.name,
+            status: run.conclusion.unwrap_or(match run.status {
+                Some(graphql::CheckRunStatus::Queued) => CheckStatus::Queued,
+                Some(graphql::CheckRunStatus::InProgress) => CheckStatus::InProgress,
+                Some(graphql::CheckRunStatus::Waiting) => CheckStatus::Waiting,
+                Some(graphql::CheckRunStatus::Requested) => CheckStatus::Requested,
+                _ => CheckStatus::Pending,
+            }),
+            url: Some(run.url),
+            required: true,
+            started_at: run.started_at,
+            completed_at: run.completed_at,
+            usual_duration: None,
+            failure: run.annotations.map(|annotations| Failure {
+                title: run.title,
+                summary: run.summary,
+                annotations: annotations
+                    .nodes
+                    .into_iter()
+                    .map(Annotation::from)
+                    .collect(),
+            }),
+            check_suite: None,
+        }
     }
 }
 
@@ -232,7 +767,7 @@ mod tests {
 
         #[test]
         fn approved() {
-            assert!(fixture().approved)
+            assert!(fixture().approved())
         }
 
         #[test]
@@ -252,17 +787,35 @@ mod tests {
                     Check {
                         name: "Status 1".into(),
                         status: CheckStatus::Success,
-                        url: "https://url".into()
+                        url: Some("https://url".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
                     },
                     Check {
                         name: "Status 2".into(),
                         status: CheckStatus::Success,
-                        url: "https://url".into()
+                        url: Some("https://url".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
                     },
                     Check {
                         name: "Check 1".into(),
                         status: CheckStatus::Success,
-                        url: "https://github.com/org/repo/runs/1".into()
+                        url: Some("https://github.com/org/repo/runs/1".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
                     },
                 ],
                 fixture().checks
@@ -297,7 +850,7 @@ mod tests {
 
         #[test]
         fn approved() {
-            assert!(!fixture().approved)
+            assert!(!fixture().approved())
         }
 
         #[test]
@@ -317,12 +870,24 @@ mod tests {
                     Check {
                         name: "Check 1".into(),
                         status: CheckStatus::Failure,
-                        url: "https://github.com/org/repo/runs/1".into()
+                        url: Some("https://github.com/org/repo/runs/1".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
                     },
                     Check {
                         name: "Check 2".into(),
                         status: CheckStatus::Cancelled,
-                        url: "https://github.com/org/repo/runs/2".into()
+                        url: Some("https://github.com/org/repo/runs/2".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
                     },
                 ],
                 fixture().checks
@@ -357,7 +922,7 @@ mod tests {
 
         #[test]
         fn approved() {
-            assert!(fixture().approved)
+            assert!(fixture().approved())
         }
 
         #[test]
@@ -377,12 +942,24 @@ mod tests {
                     Check {
                         name: "Check 1".into(),
                         status: CheckStatus::Failure,
-                        url: "https://github.com/org/repo/runs/1".into()
+                        url: Some("https://github.com/org/repo/runs/1".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
                     },
                     Check {
                         name: "Check 2".into(),
                         status: CheckStatus::Failure,
-                        url: "https://github.com/org/repo/runs/2".into()
+                        url: Some("https://github.com/org/repo/runs/2".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
                     },
                 ],
                 fixture().checks
@@ -417,7 +994,7 @@ mod tests {
 
         #[test]
         fn approved() {
-            assert!(!fixture().approved)
+            assert!(!fixture().approved())
         }
 
         #[test]
@@ -464,7 +1041,7 @@ mod tests {
 
         #[test]
         fn approved() {
-            assert!(!fixture().approved)
+            assert!(!fixture().approved())
         }
 
         #[test]
@@ -488,4 +1065,751 @@ mod tests {
             assert_eq!(xbar::Status::Unknown, fixture().status())
         }
     }
+
+    mod conflicting {
+        use super::*;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_conflicting.json"))
+        }
+
+        #[test]
+        fn approved() {
+            assert!(fixture().approved())
+        }
+
+        #[test]
+        fn mergeable() {
+            assert_eq!(
+                Some(graphql::MergeableState::Conflicting),
+                fixture().mergeable
+            )
+        }
+
+        #[test]
+        fn overall_status() {
+            assert_eq!(Some(CheckStatus::Success), fixture().overall_status)
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(xbar::Status::Conflicting, fixture().status())
+        }
+    }
+
+    mod behind {
+        use super::*;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_behind.json"))
+        }
+
+        #[test]
+        fn merge_state_status() {
+            assert_eq!(
+                Some(graphql::MergeStateStatus::Behind),
+                fixture().merge_state_status
+            )
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(xbar::Status::BehindBase, fixture().status())
+        }
+    }
+
+    mod changes_requested {
+        use super::*;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_changes_requested.json"))
+        }
+
+        #[test]
+        fn approved() {
+            assert!(!fixture().approved())
+        }
+
+        #[test]
+        fn review_status() {
+            assert_eq!(ReviewStatus::ChangesRequested, fixture().review_status)
+        }
+
+        #[test]
+        fn changes_requested_by() {
+            assert_eq!(vec!["alice".to_string()], fixture().changes_requested_by)
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(xbar::Status::ChangesRequested, fixture().status())
+        }
+    }
+
+    mod review_status {
+        use super::*;
+
+        fn review(state: graphql::ReviewState) -> graphql::Review {
+            graphql::Review {
+                state,
+                author: None,
+                submitted_at: None,
+                url: None,
+                commit: None,
+            }
+        }
+
+        #[test]
+        fn decision_wins() {
+            assert_eq!(
+                ReviewStatus::Approved,
+                ReviewStatus::from_reviews(
+                    Some(graphql::ReviewDecision::Approved),
+                    &[review(graphql::ReviewState::ChangesRequested)],
+                    false
+                )
+            )
+        }
+
+        #[test]
+        fn falls_back_to_reviews_without_a_decision() {
+            assert_eq!(
+                ReviewStatus::ChangesRequested,
+                ReviewStatus::from_reviews(
+                    None,
+                    &[
+                        review(graphql::ReviewState::Approved),
+                        review(graphql::ReviewState::ChangesRequested)
+                    ],
+                    false
+                )
+            )
+        }
+
+        #[test]
+        fn commented_only() {
+            assert_eq!(
+                ReviewStatus::Commented,
+                ReviewStatus::from_reviews(
+                    Some(graphql::ReviewDecision::ReviewRequired),
+                    &[],
+                    true
+                )
+            )
+        }
+
+        #[test]
+        fn review_required() {
+            assert_eq!(
+                ReviewStatus::ReviewRequired,
+                ReviewStatus::from_reviews(
+                    Some(graphql::ReviewDecision::ReviewRequired),
+                    &[],
+                    false
+                )
+            )
+        }
+
+        #[test]
+        fn not_reviewed() {
+            assert_eq!(
+                ReviewStatus::NotReviewed,
+                ReviewStatus::from_reviews(None, &[], false)
+            )
+        }
+    }
+
+    mod awaiting_review {
+        use super::*;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_awaiting_review.json"))
+        }
+
+        #[test]
+        fn reviewers() {
+            assert_eq!(
+                vec!["alice".to_string(), "platform-team".to_string()],
+                fixture().reviewers
+            )
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(
+                xbar::Status::SuccessAwaitingApproval(vec![
+                    "alice".to_string(),
+                    "platform-team".to_string()
+                ]),
+                fixture().status()
+            )
+        }
+    }
+
+    mod reviews {
+        use super::*;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_reviewed.json"))
+        }
+
+        #[test]
+        fn reviews() {
+            assert_eq!(
+                vec![
+                    Review {
+                        reviewer: "alice".into(),
+                        state: graphql::ReviewState::Approved,
+                        submitted_at: Some(
+                            DateTime::parse_from_rfc3339("2022-01-18T18:00:00Z").unwrap()
+                        ),
+                        url: Some("https://github.com/org/repo/pull/1#pullrequestreview-1".into()),
+                        stale: false,
+                    },
+                    Review {
+                        reviewer: "bob".into(),
+                        state: graphql::ReviewState::Commented,
+                        submitted_at: Some(
+                            DateTime::parse_from_rfc3339("2022-01-17T12:00:00Z").unwrap()
+                        ),
+                        url: Some("https://github.com/org/repo/pull/1#pullrequestreview-2".into()),
+                        stale: true,
+                    },
+                ],
+                fixture().reviews
+            )
+        }
+
+        #[test]
+        fn stale_review_line() {
+            let line = fixture().reviews[1].to_xbar_line();
+
+            assert!(line.starts_with("bob: commented "), "{line}");
+            assert!(
+                line.ends_with(
+                    " ago (stale) | href=https://github.com/org/repo/pull/1#pullrequestreview-2"
+                ),
+                "{line}"
+            );
+        }
+    }
+
+    mod open_threads {
+        use super::*;
+        use clap::Parser;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_open_threads.json"))
+        }
+
+        #[test]
+        fn unresolved_threads() {
+            assert_eq!(2, fixture().unresolved_threads)
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(xbar::Status::OpenThreads, fixture().status())
+        }
+
+        #[test]
+        fn menu_line() {
+            let emoji = xbar::Emoji::parse_from(["test"]);
+
+            assert!(fixture()
+                .to_xbar_menu(&emoji, None)
+                .starts_with("💬 Title 💬2 | href="))
+        }
+    }
+
+    mod optional_checks {
+        use super::*;
+        use clap::Parser;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_optional_failing.json"))
+        }
+
+        #[test]
+        fn required_checks_first() {
+            assert_eq!(
+                vec![
+                    Check {
+                        name: "build".into(),
+                        status: CheckStatus::Success,
+                        url: Some("https://github.com/org/repo/runs/2".into()),
+                        required: true,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
+                    },
+                    Check {
+                        name: "lint".into(),
+                        status: CheckStatus::Failure,
+                        url: Some("https://github.com/org/repo/runs/1".into()),
+                        required: false,
+                        started_at: None,
+                        completed_at: None,
+                        usual_duration: None,
+                        failure: None,
+                        check_suite: None,
+                    },
+                ],
+                fixture().checks
+            )
+        }
+
+        #[test]
+        fn status_ignores_optional_failures() {
+            assert_eq!(xbar::Status::SuccessAndApproved, fixture().status())
+        }
+
+        #[test]
+        fn optional_failures_are_marked() {
+            let emoji = xbar::Emoji::parse_from(["test"]);
+
+            assert!(fixture().to_xbar_menu(&emoji, None).ends_with(
+                "-- 🌑 lint (optional) | href=https://github.com/org/repo/runs/1 color=gray"
+            ))
+        }
+    }
+
+    mod nothing_required {
+        use super::*;
+        use clap::Parser;
+
+        fn fixture() -> PullRequest {
+            load(include_str!(
+                "test_fixtures/pr_nothing_required_failing.json"
+            ))
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(xbar::Status::Failure, fixture().status())
+        }
+
+        #[test]
+        fn checks_are_not_optional() {
+            assert!(fixture().checks.iter().all(|check| check.required))
+        }
+
+        #[test]
+        fn no_merge_action() {
+            let emoji = xbar::Emoji::parse_from(["test"]);
+            let callback =
+                xbar::Callback::new("xbar-pr-status", "https://api.github.com/graphql", None);
+
+            assert!(!fixture()
+                .to_xbar_menu(&emoji, Some(&callback))
+                .contains("Merge"))
+        }
+    }
+
+    mod expected {
+        use super::*;
+        use clap::Parser;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_expected.json"))
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(xbar::Status::Expected, fixture().status())
+        }
+
+        #[test]
+        fn expected_check() {
+            assert_eq!(
+                Check {
+                    name: "Status 2".into(),
+                    status: CheckStatus::Expected,
+                    url: None,
+                    required: true,
+                    started_at: None,
+                    completed_at: None,
+                    usual_duration: None,
+                    failure: None,
+                    check_suite: None,
+                },
+                fixture().checks[1]
+            )
+        }
+
+        #[test]
+        fn menu() {
+            let emoji = xbar::Emoji::parse_from(["test"]);
+            let menu = fixture().to_xbar_menu(&emoji, None);
+
+            assert!(menu.starts_with("🌒 Title | href="), "{menu}");
+            assert!(
+                menu.contains("\n-- 🌒 Status 2 (waiting for check to start) | color=gray\n"),
+                "{menu}"
+            );
+        }
+    }
+
+    mod durations {
+        use super::*;
+        use clap::Parser;
+
+        fn check(started_at: &str, completed_at: Option<&str>) -> Check {
+            let parse = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+
+            Check {
+                name: "build".into(),
+                status: if completed_at.is_some() {
+                    CheckStatus::Success
+                } else {
+                    CheckStatus::InProgress
+                },
+                url: None,
+                required: true,
+                started_at: Some(parse(started_at)),
+                completed_at: completed_at.map(parse),
+                usual_duration: Some(TimeDelta::minutes(4)),
+                failure: None,
+                check_suite: None,
+            }
+        }
+
+        fn now() -> DateTime<Utc> {
+            "2022-01-18T19:00:00Z".parse().unwrap()
+        }
+
+        #[test]
+        fn completed() {
+            assert_eq!(
+                "🌕 build (4m12s)",
+                check("2022-01-18T18:00:00Z", Some("2022-01-18T18:04:12Z"))
+                    .to_xbar_line(&xbar::Emoji::parse_from(["test"]), now())
+            )
+        }
+
+        #[test]
+        fn running() {
+            assert_eq!(
+                "🌓 build — running for 3m",
+                check("2022-01-18T18:57:00Z", None)
+                    .to_xbar_line(&xbar::Emoji::parse_from(["test"]), now())
+            )
+        }
+
+        #[test]
+        fn slower_than_usual() {
+            assert_eq!(
+                "🌓 build — running for 9m 🐢 usually 4m",
+                check("2022-01-18T18:51:00Z", None)
+                    .to_xbar_line(&xbar::Emoji::parse_from(["test"]), now())
+            )
+        }
+    }
+
+    mod check_queued {
+        use super::*;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_check_queued.json"))
+        }
+
+        #[test]
+        fn check_status() {
+            assert_eq!(CheckStatus::Queued, fixture().checks[2].status)
+        }
+
+        #[test]
+        fn status() {
+            assert_eq!(xbar::Status::CheckQueued, fixture().status())
+        }
+    }
+
+    mod failure_details {
+        use super::*;
+        use clap::Parser;
+
+        fn fixture() -> PullRequest {
+            load(include_str!("test_fixtures/pr_failing_annotated.json"))
+        }
+
+        #[test]
+        fn annotations_link_to_the_line() {
+            assert_eq!(
+                Some(Failure {
+                    title: Some("2 tests failed".into()),
+                    summary: Some("\n## Test results\n\nSee the log for details.".into()),
+                    annotations: vec![Annotation {
+                        path: "src/lib.rs".into(),
+                        line: 42,
+                        message: "assertion failed: left == right\nleft: 1".into(),
+                        url: "https://github.com/org/repo/blob/abc123/src/lib.rs#L42".into(),
+                    }],
+                }),
+                fixture().checks[0].failure
+            )
+        }
+
+        #[test]
+        fn menu() {
+            let emoji = xbar::Emoji::parse_from(["test"]);
+
+            assert!(fixture().to_xbar_menu(&emoji, None).contains(concat!(
+                "\n---- 2 tests failed",
+                "\n---- ## Test results | color=gray",
+                "\n---- src/lib.rs:42: assertion failed: left == right | href=https://github.com/org/repo/blob/abc123/src/lib.rs#L42\n",
+            )))
+        }
+
+        #[test]
+        fn rerun_action() {
+            let emoji = xbar::Emoji::parse_from(["test"]);
+            let callback = xbar::Callback::new("xbar-pr-status", "https://api", None);
+
+            assert!(fixture()
+                .to_xbar_menu(&emoji, Some(&callback))
+                .contains("\n---- Re-run failed jobs | shell=\"xbar-pr-status\" param1=\"--action-api-url\" param2=\"https://api\" param3=\"rerun\" param4=\"--check-suite\" param5=\"CS_1\" terminal=false refresh=true"))
+        }
+
+        #[test]
+        fn no_rerun_action_without_a_callback() {
+            let emoji = xbar::Emoji::parse_from(["test"]);
+
+            assert!(!fixture().to_xbar_menu(&emoji, None).contains("Re-run"))
+        }
+    }
+
+    mod merge_actions {
+        use super::*;
+
+        fn fixture() -> Value {
+            serde_json::from_str(include_str!("test_fixtures/pr_mergeable.json")).unwrap()
+        }
+
+        fn actions(value: Value) -> Vec<String> {
+            let callback = xbar::Callback::new("xbar-pr-status", "https://api", None);
+
+            PullRequest::try_from(&value)
+                .unwrap()
+                .merge_actions("PR_1", &callback)
+                .into_iter()
+                .map(|line| line.split(" | ").next().unwrap().to_string())
+                .collect()
+        }
+
+        #[test]
+        fn merge_when_approved_and_green() {
+            assert_eq!(
+                vec![
+                    "-- Merge",
+                    "---- Squash and merge",
+                    "---- Create a merge commit"
+                ],
+                actions(fixture())
+            )
+        }
+
+        #[test]
+        fn merge_action_params() {
+            let callback = xbar::Callback::new("xbar-pr-status", "https://api", None);
+            let pr = PullRequest::try_from(&fixture()).unwrap();
+
+            assert!(pr.merge_actions("PR_1", &callback)[1].ends_with(
+                r#"param3="merge" param4="--pull-request" param5="PR_1" param6="--method" param7="squash" terminal=false refresh=true"#
+            ))
+        }
+
+        #[test]
+        fn enqueue_when_the_base_branch_has_a_merge_queue() {
+            let mut value = fixture();
+            value["isMergeQueueEnabled"] = true.into();
+
+            assert_eq!(vec!["-- Add to merge queue"], actions(value))
+        }
+
+        #[test]
+        fn auto_merge_while_checks_are_pending() {
+            let mut value = fixture();
+            value["commits"]["nodes"][0]["commit"]["statusCheckRollup"]["state"] = "PENDING".into();
+
+            assert_eq!(
+                vec![
+                    "-- Enable auto-merge",
+                    "---- Squash and merge",
+                    "---- Create a merge commit"
+                ],
+                actions(value)
+            )
+        }
+
+        #[test]
+        fn nothing_while_failing() {
+            let mut value = fixture();
+            value["commits"]["nodes"][0]["commit"]["statusCheckRollup"]["state"] = "FAILURE".into();
+
+            assert!(actions(value).is_empty())
+        }
+
+        #[test]
+        fn auto_merge_already_enabled() {
+            let mut value = fixture();
+            value["commits"]["nodes"][0]["commit"]["statusCheckRollup"]["state"] = "PENDING".into();
+            value["autoMergeRequest"] = serde_json::json!({ "enabledAt": "2022-01-18T18:00:00Z" });
+
+            assert_eq!(vec!["-- Auto-merge is enabled"], actions(value))
+        }
+    }
+
+    mod draft_actions {
+        use super::*;
+        use clap::Parser;
+
+        fn menu(is_draft: bool) -> String {
+            let mut value: Value =
+                serde_json::from_str(include_str!("test_fixtures/pr_mergeable.json")).unwrap();
+            value["isDraft"] = is_draft.into();
+
+            PullRequest::try_from(&value).unwrap().to_xbar_menu(
+                &xbar::Emoji::parse_from(["test"]),
+                Some(&xbar::Callback::new("xbar-pr-status", "https://api", None)),
+            )
+        }
+
+        #[test]
+        fn ready_for_review() {
+            let menu = menu(true);
+
+            assert!(menu.contains(r#"-- Ready for review | shell="xbar-pr-status" param1="--action-api-url" param2="https://api" param3="ready" param4="--pull-request" param5="PR_1" terminal=false refresh=true"#), "{menu}");
+            assert!(!menu.contains("Convert to draft"), "{menu}");
+        }
+
+        #[test]
+        fn convert_to_draft() {
+            let menu = menu(false);
+
+            assert!(menu.contains(r#"-- Convert to draft | shell="xbar-pr-status" param1="--action-api-url" param2="https://api" param3="draft" param4="--pull-request" param5="PR_1" terminal=false refresh=true"#), "{menu}");
+            assert!(!menu.contains("Ready for review"), "{menu}");
+        }
+    }
+
+    mod request_review {
+        use super::*;
+        use clap::Parser;
+
+        fn menu(fixture: &str) -> String {
+            let emoji = xbar::Emoji::parse_from([
+                "test",
+                "--reviewer-emoji",
+                "alice=🐢",
+                "--reviewer-emoji",
+                "core-team=🐝",
+            ]);
+
+            load(fixture).to_xbar_menu(
+                &emoji,
+                Some(&xbar::Callback::new("xbar-pr-status", "https://api", None)),
+            )
+        }
+
+        #[test]
+        fn offers_the_roster() {
+            let menu = menu(include_str!("test_fixtures/pr_mergeable.json"));
+
+            assert!(menu.contains(concat!(
+                "\n-- Request review from…",
+                r#"
+---- 🐢 alice | shell="xbar-pr-status" param1="--action-api-url" param2="https://api" param3="request-review" param4="--pull-request" param5="PR_1" param6="--reviewer" param7="alice" terminal=false refresh=true"#,
+                r#"
+---- 🐝 core-team | shell="xbar-pr-status" param1="--action-api-url" param2="https://api" param3="request-review" param4="--pull-request" param5="PR_1" param6="--reviewer" param7="core-team" terminal=false refresh=true"#,
+            )), "{menu}");
+        }
+
+        #[test]
+        fn not_while_a_review_is_pending() {
+            let mut value: Value =
+                serde_json::from_str(include_str!("test_fixtures/pr_mergeable.json")).unwrap();
+            value["reviewRequests"] =
+                serde_json::json!({ "nodes": [{ "requestedReviewer": { "login": "bob" } }] });
+
+            assert!(!menu(&value.to_string()).contains("Request review from"));
+        }
+    }
+
+    mod others_prs {
+        use super::*;
+        use clap::Parser;
+
+        #[test]
+        fn no_actions_that_change_the_pr() {
+            let mut value: Value =
+                serde_json::from_str(include_str!("test_fixtures/pr_mergeable.json")).unwrap();
+            value["viewerDidAuthor"] = false.into();
+
+            let menu = PullRequest::try_from(&value).unwrap().to_xbar_menu(
+                &xbar::Emoji::parse_from(["test", "--reviewer-emoji", "alice=🐢"]),
+                Some(&xbar::Callback::new("xbar-pr-status", "https://api", None)),
+            );
+
+            for action in ["Merge", "auto-merge", "Request review", "Convert to draft"] {
+                assert!(!menu.contains(action), "{menu}");
+            }
+            assert!(menu.contains("-- Copy URL"), "{menu}");
+        }
+    }
+
+    mod copy_actions {
+        use super::*;
+        use clap::Parser;
+
+        #[test]
+        fn copies_through_the_binary() {
+            let menu = load(include_str!("test_fixtures/pr_approved.json")).to_xbar_menu(
+                &xbar::Emoji::parse_from(["test"]),
+                Some(&xbar::Callback::new("xbar-pr-status", "https://api", None)),
+            );
+
+            assert!(
+                menu.contains(
+                    r##"
+-- #1 | shell="xbar-pr-status" param1="copy" param2="#1" terminal=false refresh=false
+"##
+                ),
+                "{menu}"
+            );
+            assert!(!menu.contains("pbcopy"), "{menu}");
+        }
+
+        fn menu(branch: &str) -> String {
+            let mut value: Value =
+                serde_json::from_str(include_str!("test_fixtures/pr_approved.json")).unwrap();
+            value["headRef"]["name"] = branch.into();
+
+            PullRequest::try_from(&value).unwrap().to_xbar_menu(
+                &xbar::Emoji::parse_from(["test"]),
+                Some(&xbar::Callback::new("xbar-pr-status", "https://api", None)),
+            )
+        }
+
+        #[test]
+        fn escapes_pipes_in_branch_names() {
+            let menu = menu("fix|this");
+
+            assert!(
+                menu.contains(
+                    r#"
+-- fix\|this | shell="xbar-pr-status" param1="copy" param2="fix|this" terminal=false refresh=false"#
+                ),
+                "{menu}"
+            );
+        }
+
+        #[test]
+        fn skips_branch_names_with_quotes() {
+            let menu = menu(r#"say-"hi""#);
+
+            assert!(!menu.contains("say-"), "{menu}");
+            assert!(menu.contains("\n-- #1 | "), "{menu}");
+        }
+    }
 }
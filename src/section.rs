@@ -0,0 +1,58 @@
+use anyhow::{Context, Result};
+use std::str::FromStr;
+
+/// A heading in the dropdown, filled with the PRs matching a GitHub issue
+/// search query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub query: String,
+}
+
+impl FromStr for Section {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, query) = s.split_once('=').with_context(|| {
+            format!("I couldn't find a `=` between the name and query in `{s}`")
+        })?;
+
+        Ok(Section {
+            name: name.trim().to_string(),
+            query: query.trim().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_and_query() {
+        assert_eq!(
+            Section {
+                name: "Release train".into(),
+                query: "org:acme is:pr is:open label:release-blocker".into(),
+            },
+            Section::from_str("Release train=org:acme is:pr is:open label:release-blocker")
+                .unwrap()
+        )
+    }
+
+    #[test]
+    fn equals_in_query() {
+        assert_eq!(
+            "is:pr label:a=b",
+            Section::from_str("Labelled=is:pr label:a=b").unwrap().query
+        )
+    }
+
+    #[test]
+    fn no_equals() {
+        assert_eq!(
+            "I couldn't find a `=` between the name and query in `nope`",
+            Section::from_str("nope").unwrap_err().to_string()
+        )
+    }
+}
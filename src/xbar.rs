@@ -1,26 +1,39 @@
 use crate::check_status::CheckStatus;
 use anyhow::{Context, Result};
+use chrono::TimeDelta;
 use clap::Parser;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Status {
     SuccessAndApproved,
-    SuccessAwaitingApproval(String),
+    SuccessAwaitingApproval(Vec<String>),
     Draft,
     Success,
     Pending,
+    /// A check is waiting for a runner
+    CheckQueued,
+    /// A check is running
+    Running,
+    /// A required check hasn't started (or reported back) yet
+    Expected,
     Failure,
     Unknown,
     NeedsAttention,
     Error,
     Queued(u64),
+    Conflicting,
+    BehindBase,
+    Blocked,
+    ChangesRequested,
+    /// Approved, but there are review threads nobody has resolved yet
+    OpenThreads,
 }
 
 impl From<&CheckStatus> for Status {
     fn from(status: &CheckStatus) -> Self {
         match &status {
             CheckStatus::Error => Status::Error,
-            CheckStatus::Expected => todo!("What does expected status mean?"),
+            CheckStatus::Expected => Status::Expected,
             CheckStatus::Failure => Status::Failure,
             CheckStatus::Pending => Status::Pending,
             CheckStatus::Success => Status::Success,
@@ -31,6 +44,10 @@ impl From<&CheckStatus> for Status {
             CheckStatus::Skipped => Status::Success,
             CheckStatus::StartupFailure => Status::Error,
             CheckStatus::Stale => Status::Error,
+            CheckStatus::Queued => Status::CheckQueued,
+            CheckStatus::Requested => Status::CheckQueued,
+            CheckStatus::InProgress => Status::Running,
+            CheckStatus::Waiting => Status::NeedsAttention,
         }
     }
 }
@@ -52,6 +69,18 @@ pub struct Emoji {
     #[clap(long, env = "PENDING_EMOJI", default_value = "🌓")]
     pending_emoji: String,
 
+    /// Emoji to use when a check is waiting for a runner to pick it up
+    #[clap(long, env = "CHECK_QUEUED_EMOJI", default_value = "🌘")]
+    check_queued_emoji: String,
+
+    /// Emoji to use when a check is running
+    #[clap(long, env = "RUNNING_EMOJI", default_value = "🌓")]
+    running_emoji: String,
+
+    /// Emoji to use when we're waiting for a check to start
+    #[clap(long, env = "EXPECTED_EMOJI", default_value = "🌒")]
+    expected_emoji: String,
+
     /// Emoji to use when CI fails
     #[clap(long, env = "FAILURE_EMOJI", default_value = "🌑")]
     failure_emoji: String,
@@ -72,8 +101,45 @@ pub struct Emoji {
     #[clap(long, env = "QUEUED_EMOJI", default_value = "✨")]
     queued_emoji: String,
 
+    /// Emoji to use when a reviewer has requested changes
+    #[clap(long, env = "CHANGES_REQUESTED_EMOJI", default_value = "📝")]
+    changes_requested_emoji: String,
+
+    /// Emoji to use when the PR has merge conflicts with its base branch
+    #[clap(long, env = "CONFLICTING_EMOJI", default_value = "⚔️")]
+    conflicting_emoji: String,
+
+    /// Emoji to use when the PR has to be updated with its base branch
+    /// before it can merge
+    #[clap(long, env = "BEHIND_BASE_EMOJI", default_value = "⏪")]
+    behind_base_emoji: String,
+
+    /// Emoji to use when the PR is approved and passing CI but something
+    /// else (like branch protection) is blocking the merge
+    #[clap(long, env = "BLOCKED_EMOJI", default_value = "🚫")]
+    blocked_emoji: String,
+
+    /// Emoji to use when the PR is approved but has unresolved review
+    /// threads. Also shown next to the count of unresolved threads.
+    #[clap(long, env = "OPEN_THREADS_EMOJI", default_value = "💬")]
+    open_threads_emoji: String,
+
+    /// Emoji to show next to the count of PRs waiting on your review
+    #[clap(long, env = "REVIEW_REQUESTED_EMOJI", default_value = "👀")]
+    review_requested_emoji: String,
+
+    /// Emoji to show in front of everything else when GitHub can't be
+    /// reached and we're showing PRs from the last time it could
+    #[clap(long, env = "STALE_EMOJI", default_value = "💤")]
+    stale_emoji: String,
+
+    /// Emoji to flag checks that are taking much longer than usual
+    #[clap(long, env = "SLOW_CHECK_EMOJI", default_value = "🐢")]
+    slow_check_emoji: String,
+
     /// Emoji for a specific reviewer while waiting for review. Format is
-    /// reviewerGithubLogin=EMOJI
+    /// reviewerGithubLogin=EMOJI. Use the team slug for team reviewers. If
+    /// we're waiting on several reviewers, the first one with an emoji wins.
     #[clap(long("reviewer-emoji"), value_parser = parse_reviewer, action = clap::ArgAction::Append)]
     reviewer_emojis: Vec<(String, String)>,
 
@@ -85,19 +151,25 @@ impl Emoji {
     pub fn for_status(&self, status: Status) -> &str {
         match status {
             Status::SuccessAndApproved => &self.success_and_approved_emoji,
-            Status::SuccessAwaitingApproval(reviewer) => self
-                .reviewer_emojis
+            Status::SuccessAwaitingApproval(reviewers) => reviewers
                 .iter()
-                .filter_map(|(name, emoji)| if name == &reviewer { Some(emoji) } else { None })
-                .next()
+                .find_map(|reviewer| self.for_reviewer(reviewer))
                 .unwrap_or(&self.default_reviewer_emoji),
             Status::Success => &self.success_emoji,
             Status::Draft => &self.draft_emoji,
             Status::Pending => &self.pending_emoji,
+            Status::Expected => &self.expected_emoji,
+            Status::CheckQueued => &self.check_queued_emoji,
+            Status::Running => &self.running_emoji,
             Status::Failure => &self.failure_emoji,
             Status::Unknown => &self.unknown_emoji,
             Status::NeedsAttention => &self.needs_attention_emoji,
             Status::Error => &self.error_emoji,
+            Status::Conflicting => &self.conflicting_emoji,
+            Status::BehindBase => &self.behind_base_emoji,
+            Status::Blocked => &self.blocked_emoji,
+            Status::ChangesRequested => &self.changes_requested_emoji,
+            Status::OpenThreads => &self.open_threads_emoji,
             // TODO: add emoji options for these
             Status::Queued(1) => "1️⃣",
             Status::Queued(2) => "2️⃣",
@@ -111,6 +183,121 @@ impl Emoji {
             Status::Queued(_) => &self.queued_emoji,
         }
     }
+
+    /// The emoji configured for a reviewer's login (or a team's slug), if
+    /// any.
+    pub fn for_reviewer(&self, reviewer: &str) -> Option<&str> {
+        self.reviewer_emojis
+            .iter()
+            .find(|(name, _)| name == reviewer)
+            .map(|(_, emoji)| emoji.as_str())
+    }
+
+    /// Everyone with a configured emoji, with that emoji
+    pub fn reviewers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.reviewer_emojis
+            .iter()
+            .map(|(name, emoji)| (name.as_str(), emoji.as_str()))
+    }
+
+    pub fn review_requested(&self) -> &str {
+        &self.review_requested_emoji
+    }
+
+    pub fn stale(&self) -> &str {
+        &self.stale_emoji
+    }
+
+    pub fn slow_check(&self) -> &str {
+        &self.slow_check_emoji
+    }
+}
+
+/// Runs this program again with a subcommand when a menu item is clicked,
+/// pointed at the GitHub host the item is about. The subcommand finds the
+/// token for that host itself, so tokens never end up in the menu (or on a
+/// command line.)
+#[derive(Debug, Clone)]
+pub struct Callback {
+    exe: String,
+    api_url: String,
+    copy_command: Option<String>,
+}
+
+impl Callback {
+    pub fn new(exe: &str, api_url: &str, copy_command: Option<&str>) -> Self {
+        Callback {
+            exe: exe.to_string(),
+            api_url: api_url.to_string(),
+            copy_command: copy_command.map(String::from),
+        }
+    }
+
+    /// The parameters for a menu item that runs the subcommand in `args`
+    /// against this host, then refreshes the menu. `args` are things like
+    /// node IDs and logins, which never have quotes in them.
+    pub fn params(&self, args: &[&str]) -> String {
+        self.shell(
+            &[&["--action-api-url", self.api_url.as_str()], args].concat(),
+            true,
+        )
+    }
+
+    /// The parameters for a menu item that copies `text`, or `None` if we
+    /// can't pass `text` along. Nothing on GitHub changes, so there's no
+    /// need to refresh.
+    pub fn copy_params(&self, text: &str) -> Option<String> {
+        let mut args = vec!["copy", text];
+        if let Some(command) = &self.copy_command {
+            args.extend(["--command", command]);
+        }
+
+        // xbar has no way to escape a quote in a parameter
+        if args.iter().any(|arg| arg.contains('"')) {
+            return None;
+        }
+
+        Some(self.shell(&args, false))
+    }
+
+    fn shell(&self, args: &[&str], refresh: bool) -> String {
+        let mut params = vec![format!("shell=\"{}\"", self.exe)];
+
+        for (i, arg) in args.iter().enumerate() {
+            params.push(format!("param{}=\"{}\"", i + 1, arg));
+        }
+
+        params.push(format!("terminal=false refresh={refresh}"));
+        params.join(" ")
+    }
+}
+
+/// Format a duration compactly, like `14m` or `2h5m`.
+pub fn format_duration(duration: TimeDelta) -> String {
+    let seconds = duration.num_seconds().max(0);
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+
+    if days > 0 {
+        format!("{days}d{hours}h")
+    } else if hours > 0 {
+        format!("{hours}h{minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Like `format_duration`, but keeps the seconds for anything under an hour,
+/// like `4m12s`. CI jobs are usually short enough for that to matter.
+pub fn format_elapsed(duration: TimeDelta) -> String {
+    let seconds = duration.num_seconds().max(0);
+
+    if (60..3600).contains(&seconds) && seconds % 60 != 0 {
+        format!("{}m{}s", seconds / 60, seconds % 60)
+    } else {
+        format_duration(duration)
+    }
 }
 
 fn parse_reviewer(s: &str) -> Result<(String, String)> {
@@ -126,3 +313,135 @@ fn parse_reviewer(s: &str) -> Result<(String, String)> {
             .to_string(),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod for_status {
+        use super::*;
+
+        fn emoji() -> Emoji {
+            Emoji::parse_from([
+                "test",
+                "--reviewer-emoji",
+                "alice=🐇",
+                "--reviewer-emoji",
+                "platform-team=🛠️",
+            ])
+        }
+
+        #[test]
+        fn first_reviewer_with_an_emoji() {
+            assert_eq!(
+                "🛠️",
+                emoji().for_status(Status::SuccessAwaitingApproval(vec![
+                    "bob".into(),
+                    "platform-team".into(),
+                    "alice".into()
+                ]))
+            )
+        }
+
+        #[test]
+        fn default_reviewer() {
+            assert_eq!(
+                "🌜",
+                emoji().for_status(Status::SuccessAwaitingApproval(vec!["bob".into()]))
+            )
+        }
+    }
+
+    mod format_duration {
+        use super::*;
+
+        #[test]
+        fn seconds() {
+            assert_eq!("42s", format_duration(TimeDelta::seconds(42)))
+        }
+
+        #[test]
+        fn minutes() {
+            assert_eq!("14m", format_duration(TimeDelta::seconds(14 * 60 + 5)))
+        }
+
+        #[test]
+        fn hours() {
+            assert_eq!("2h5m", format_duration(TimeDelta::minutes(125)))
+        }
+
+        #[test]
+        fn days() {
+            assert_eq!("1d3h", format_duration(TimeDelta::hours(27)))
+        }
+
+        #[test]
+        fn negative() {
+            assert_eq!("0s", format_duration(TimeDelta::seconds(-5)))
+        }
+    }
+
+    mod format_elapsed {
+        use super::*;
+
+        #[test]
+        fn seconds() {
+            assert_eq!("42s", format_elapsed(TimeDelta::seconds(42)))
+        }
+
+        #[test]
+        fn minutes_and_seconds() {
+            assert_eq!("4m12s", format_elapsed(TimeDelta::seconds(4 * 60 + 12)))
+        }
+
+        #[test]
+        fn hours() {
+            assert_eq!("2h5m", format_elapsed(TimeDelta::minutes(125)))
+        }
+    }
+
+    mod callback {
+        use super::*;
+
+        #[test]
+        fn params() {
+            assert_eq!(
+                r#"shell="/bin/xbar-pr-status" param1="--action-api-url" param2="https://api.github.com/graphql" param3="rerun" param4="--check-suite" param5="CS_1" terminal=false refresh=true"#,
+                Callback::new(
+                    "/bin/xbar-pr-status",
+                    "https://api.github.com/graphql",
+                    None
+                )
+                .params(&["rerun", "--check-suite", "CS_1"])
+            )
+        }
+
+        #[test]
+        fn copy_params() {
+            assert_eq!(
+                Some(
+                    r##"shell="/bin/xbar-pr-status" param1="copy" param2="#1" param3="--command" param4="xclip -i" terminal=false refresh=false"##.to_string()
+                ),
+                Callback::new(
+                    "/bin/xbar-pr-status",
+                    "https://api.github.com/graphql",
+                    Some("xclip -i")
+                )
+                .copy_params("#1")
+            )
+        }
+
+        #[test]
+        fn cannot_copy_quotes() {
+            assert_eq!(
+                None,
+                Callback::new(
+                    "/bin/xbar-pr-status",
+                    "https://api.github.com/graphql",
+                    None
+                )
+                .copy_params(r#"say-"hi""#)
+            )
+        }
+    }
+}